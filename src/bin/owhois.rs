@@ -4,8 +4,20 @@ extern crate ipnet;
 extern crate owhois;
 
 use getopts::Options;
+use ipnet::IpNet;
+use owhois::config::{Config, ReferralOutput};
+use owhois::listener::ListenAddress;
+use owhois::policy::{AccessList, QueryPolicy};
+use owhois::ratelimit::LimitAction;
+use owhois::resolver::{AddressFamily, HostsTable};
+use owhois::target::UpstreamTarget;
+use owhois::tls::TlsConfig;
+use owhois::upstream::UpstreamConfig;
 
 use std::env;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
 
 fn print_usage(program: &str, opts: Options) {
     let brief = format!("Usage: {} [options]", program);
@@ -21,6 +33,42 @@ fn main() {
     let mut opts = Options::new();
     opts.optopt("", "default-server", "default Whois server for unhandled resources", "HOSTNAME");
     opts.optopt("", "port", "server port number", "PORT");
+    opts.optmulti("", "listen", "address to listen on, e.g. '0.0.0.0:43', '[::]:43' or 'unix:/path' (may be repeated)", "ADDRESS");
+    opts.optmulti("", "tls-listen", "address to listen on for TLS connections (may be repeated)", "ADDRESS");
+    opts.optopt("", "tls-certificate", "TLS certificate chain (PEM)", "FILE");
+    opts.optopt("", "tls-key", "TLS private key (PEM)", "FILE");
+    opts.optopt("", "tls-client-ca", "require TLS client certificates signed by these CAs (PEM)", "FILE");
+    opts.optmulti("", "proxy-protocol", "accept PROXY protocol headers from these addresses (may be repeated)", "PREFIX");
+    opts.optopt("", "max-referrals", "maximum number of referrals to follow (default 3)", "COUNT");
+    opts.optopt("", "referral-output", "return the 'final' response only, or the 'chain' of responses", "MODE");
+    opts.optopt("", "upstream-config", "per-server settings for upstream servers", "FILE");
+    opts.optopt("", "connect-timeout", "upstream connection timeout (default 5)", "SECONDS");
+    opts.optopt("", "first-byte-timeout", "upstream first byte timeout (default 15)", "SECONDS");
+    opts.optopt("", "total-timeout", "upstream total query timeout (default 30)", "SECONDS");
+    opts.optopt("", "cache-size", "maximum size of the response cache (default 0, disabled)", "BYTES");
+    opts.optopt("", "cache-ttl", "response cache TTL (default 3600)", "SECONDS");
+    opts.optopt("", "cache-negative-ttl", "response cache TTL for negative responses (default 300)", "SECONDS");
+    opts.optflag("", "cache-serve-stale", "use expired cache entries when upstream queries fail");
+    opts.optopt("", "max-connections", "maximum concurrent connections per upstream server (default 0, unlimited)", "COUNT");
+    opts.optopt("", "queue-timeout", "maximum time to wait for an upstream connection (default 10)", "SECONDS");
+    opts.optopt("", "client-rate", "queries per second permitted for each client (default 0, unlimited)", "RATE");
+    opts.optopt("", "client-burst", "queries permitted in a burst for each client (default 10)", "COUNT");
+    opts.optopt("", "client-ipv4-prefix", "prefix length for aggregating IPv4 clients (default 32)", "LENGTH");
+    opts.optopt("", "client-ipv6-prefix", "prefix length for aggregating IPv6 clients (default 64)", "LENGTH");
+    opts.optopt("", "client-max-connections", "maximum concurrent connections for each client (default 0, unlimited)", "COUNT");
+    opts.optopt("", "client-limit-action", "action when a client limit is reached: 'reject', 'tarpit' or 'ban'", "ACTION");
+    opts.optopt("", "client-tarpit-delay", "delay before rejecting tarpitted clients (default 10)", "SECONDS");
+    opts.optopt("", "client-ban-time", "duration of client bans (default 300)", "SECONDS");
+    opts.optopt("", "client-acl", "client access control list", "FILE");
+    opts.optopt("", "query-policy", "query policy rules", "FILE");
+    opts.optopt("", "address-family", "address family for upstream connections: 'any', 'prefer-ipv4', 'prefer-ipv6', 'ipv4' or 'ipv6'", "FAMILY");
+    opts.optopt("", "dns-cache-ttl", "maximum time for which upstream addresses are cached (default 60)", "SECONDS");
+    opts.optopt("", "hosts-file", "static upstream hostname to address mappings", "FILE");
+    opts.optopt("", "max-query-length", "maximum length of a query (default 1024)", "BYTES");
+    opts.optopt("", "idle-timeout", "maximum time to wait for a query (default 30)", "SECONDS");
+    opts.optopt("", "max-fan-out", "maximum number of servers to query for a resource split across servers (default 8)", "COUNT");
+    opts.optflag("", "embedded-ipv4", "route IPv6 addresses with an embedded IPv4 address by the IPv4 address");
+    opts.optopt("", "bulk-concurrency", "maximum concurrent upstream queries per bulk request (default 8)", "COUNT");
     opts.optflag("h", "help", "print this help menu");
     let matches = match opts.parse(&args[1..]) {
         Ok(m)  => { m }
@@ -30,8 +78,143 @@ fn main() {
         print_usage(&program, opts);
         return;
     }
-    let hostname = matches.opt_str("default-server");
-    let port = matches.opt_str("port");
+    let mut config = Config::default();
+    if let Some(hostname) = matches.opt_str("default-server") {
+        config.default_server =
+            hostname.parse::<UpstreamTarget>().unwrap().to_string();
+    }
+    if let Some(port) = matches.opt_str("port") {
+        config.port = port;
+    }
+    if let Some(count) = matches.opt_str("max-referrals") {
+        config.max_referrals = count.parse().unwrap();
+    }
+    if let Some(mode) = matches.opt_str("referral-output") {
+        config.referral_output =
+            match mode.as_str() {
+                "final" => ReferralOutput::Final,
+                "chain" => ReferralOutput::Chain,
+                _       => panic!("invalid referral output mode: {}", mode)
+            };
+    }
+    if let Some(seconds) = matches.opt_str("connect-timeout") {
+        config.timeouts.connect = Duration::from_secs(seconds.parse().unwrap());
+    }
+    if let Some(seconds) = matches.opt_str("first-byte-timeout") {
+        config.timeouts.first_byte = Duration::from_secs(seconds.parse().unwrap());
+    }
+    if let Some(seconds) = matches.opt_str("total-timeout") {
+        config.timeouts.total = Duration::from_secs(seconds.parse().unwrap());
+    }
+    if let Some(size) = matches.opt_str("cache-size") {
+        config.cache.max_size = size.parse().unwrap();
+    }
+    if let Some(seconds) = matches.opt_str("cache-ttl") {
+        config.cache.ttl = Duration::from_secs(seconds.parse().unwrap());
+    }
+    if let Some(seconds) = matches.opt_str("cache-negative-ttl") {
+        config.cache.negative_ttl = Duration::from_secs(seconds.parse().unwrap());
+    }
+    config.cache.serve_stale = matches.opt_present("cache-serve-stale");
+    if let Some(count) = matches.opt_str("max-connections") {
+        config.max_connections = count.parse().unwrap();
+    }
+    if let Some(seconds) = matches.opt_str("queue-timeout") {
+        config.queue_timeout = Duration::from_secs(seconds.parse().unwrap());
+    }
+    if let Some(rate) = matches.opt_str("client-rate") {
+        config.rate_limit.rate = rate.parse().unwrap();
+    }
+    if let Some(count) = matches.opt_str("client-burst") {
+        config.rate_limit.burst = count.parse().unwrap();
+    }
+    if let Some(length) = matches.opt_str("client-ipv4-prefix") {
+        config.rate_limit.ipv4_prefix_len = length.parse().unwrap();
+    }
+    if let Some(length) = matches.opt_str("client-ipv6-prefix") {
+        config.rate_limit.ipv6_prefix_len = length.parse().unwrap();
+    }
+    if let Some(count) = matches.opt_str("client-max-connections") {
+        config.rate_limit.max_connections = count.parse().unwrap();
+    }
+    if let Some(action) = matches.opt_str("client-limit-action") {
+        config.rate_limit.action =
+            match action.as_str() {
+                "reject" => LimitAction::Reject,
+                "tarpit" => LimitAction::Tarpit,
+                "ban"    => LimitAction::Ban,
+                _        => panic!("invalid client limit action: {}", action)
+            };
+    }
+    if let Some(seconds) = matches.opt_str("client-tarpit-delay") {
+        config.rate_limit.tarpit_delay = Duration::from_secs(seconds.parse().unwrap());
+    }
+    if let Some(seconds) = matches.opt_str("client-ban-time") {
+        config.rate_limit.ban_duration = Duration::from_secs(seconds.parse().unwrap());
+    }
+    if let Some(path) = matches.opt_str("client-acl") {
+        config.access_list = Arc::new(AccessList::from_file(&path));
+    }
+    if let Some(path) = matches.opt_str("query-policy") {
+        config.query_policy = QueryPolicy::from_file(&path);
+    }
+    if let Some(family) = matches.opt_str("address-family") {
+        config.resolver.family =
+            match family.as_str() {
+                "any"         => AddressFamily::Any,
+                "prefer-ipv4" => AddressFamily::PreferIpv4,
+                "prefer-ipv6" => AddressFamily::PreferIpv6,
+                "ipv4"        => AddressFamily::Ipv4,
+                "ipv6"        => AddressFamily::Ipv6,
+                _             => panic!("invalid address family: {}", family)
+            };
+    }
+    if let Some(seconds) = matches.opt_str("dns-cache-ttl") {
+        config.resolver.cache_ttl = Duration::from_secs(seconds.parse().unwrap());
+    }
+    if let Some(path) = matches.opt_str("hosts-file") {
+        config.resolver.hosts = HostsTable::from_file(&path);
+    }
+    if let Some(length) = matches.opt_str("max-query-length") {
+        config.max_query_length = length.parse().unwrap();
+    }
+    if let Some(seconds) = matches.opt_str("idle-timeout") {
+        config.idle_timeout = Duration::from_secs(seconds.parse().unwrap());
+    }
+    if let Some(count) = matches.opt_str("max-fan-out") {
+        config.max_fan_out = count.parse().unwrap();
+    }
+    if let Some(count) = matches.opt_str("bulk-concurrency") {
+        config.bulk_concurrency = count.parse().unwrap();
+    }
+    config.embedded_ipv4 = matches.opt_present("embedded-ipv4");
+    config.listen =
+        matches.opt_strs("listen").iter()
+            .map(|address| address.parse::<ListenAddress>().unwrap())
+            .collect();
+    let tls_listen: Vec<ListenAddress> =
+        matches.opt_strs("tls-listen").iter()
+            .map(|address| address.parse::<ListenAddress>().unwrap())
+            .collect();
+    if !tls_listen.is_empty() {
+        let certificate = matches.opt_str("tls-certificate")
+            .expect("--tls-certificate is required for TLS");
+        let key = matches.opt_str("tls-key")
+            .expect("--tls-key is required for TLS");
+        config.tls = Some(TlsConfig {
+            listen:      tls_listen,
+            certificate: PathBuf::from(certificate),
+            key:         PathBuf::from(key),
+            client_ca:   matches.opt_str("tls-client-ca").map(PathBuf::from),
+        });
+    }
+    config.proxy_protocol =
+        matches.opt_strs("proxy-protocol").iter()
+            .map(|prefix| prefix.parse::<IpNet>().unwrap())
+            .collect();
+    if let Some(path) = matches.opt_str("upstream-config") {
+        config.upstream = UpstreamConfig::from_file(&path);
+    }
 
-    owhois::server::run(hostname, port);
+    owhois::server::run(config);
 }
@@ -1,10 +1,13 @@
+extern crate env_logger;
 extern crate owhois;
 
 fn main() {
+    env_logger::init();
     owhois::data::process_public(
         "data",
         "data/ipv4",
         "data/ipv6",
-        "data/asn"
+        "data/asn",
+        "data/domain"
     );
 }
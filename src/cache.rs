@@ -0,0 +1,198 @@
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/* Strings that indicate that a server has no data for the query.
+ * Responses containing any of these are cached for the (shorter)
+ * negative TTL. */
+const NEGATIVE_MARKERS: [&str; 4] = [
+    "no entries found", "no match", "no data found", "not found"
+];
+
+/* Strings that indicate that a server refused the query (e.g. due to
+ * rate limiting).  Responses containing any of these are not cached,
+ * so that the query is retried once the server permits it. */
+const ERROR_MARKERS: [&str; 7] = [
+    "access denied", "rate limit", "limit exceeded", "too many queries",
+    "too many requests", "try again later", "temporarily unavailable"
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CacheConfig {
+    /* The time for which a response is fresh. */
+    pub ttl:          Duration,
+    /* The time for which a negative response is fresh. */
+    pub negative_ttl: Duration,
+    /* The maximum total size of the cached entries, in bytes.  If
+     * zero, then nothing is cached. */
+    pub max_size:     usize,
+    /* Whether an expired entry may be returned if the upstream
+     * server cannot be queried. */
+    pub serve_stale:  bool,
+}
+
+impl Default for CacheConfig {
+    fn default() -> CacheConfig {
+        CacheConfig {
+            ttl:          Duration::from_secs(3600),
+            negative_ttl: Duration::from_secs(300),
+            max_size:     0,
+            serve_stale:  false,
+        }
+    }
+}
+
+type CacheKey = (String, String);
+
+struct CacheEntry {
+    response:  Vec<u8>,
+    expires:   Instant,
+    last_used: u64,
+}
+
+fn entry_size(key: &CacheKey, response: &[u8]) -> usize {
+    key.0.len() + key.1.len() + response.len()
+}
+
+pub fn normalise_query(query: &str) -> String {
+    query.split_whitespace()
+         .collect::<Vec<&str>>()
+         .join(" ")
+}
+
+pub fn is_negative(response: &[u8]) -> bool {
+    let text = String::from_utf8_lossy(response).to_lowercase();
+    NEGATIVE_MARKERS.iter().any(|marker| text.contains(marker))
+}
+
+pub fn is_error(response: &[u8]) -> bool {
+    let text = String::from_utf8_lossy(response).to_lowercase();
+    ERROR_MARKERS.iter().any(|marker| text.contains(marker))
+}
+
+/* Return whether the response may be cached: empty responses and
+ * errors are not cached. */
+pub fn is_cacheable(response: &[u8]) -> bool {
+    !String::from_utf8_lossy(response).trim().is_empty()
+        && !is_error(response)
+}
+
+/* A response cache keyed by (normalised query, server), with entries
+ * evicted in least-recently-used order once the size limit is
+ * reached. */
+pub struct ResponseCache {
+    config:  CacheConfig,
+    entries: HashMap<CacheKey, CacheEntry>,
+    lru:     BTreeMap<u64, CacheKey>,
+    size:    usize,
+    counter: u64,
+}
+
+impl ResponseCache {
+    pub fn new(config: CacheConfig) -> ResponseCache {
+        ResponseCache {
+            config,
+            entries: HashMap::new(),
+            lru:     BTreeMap::new(),
+            size:    0,
+            counter: 0,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.max_size > 0
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    fn touch(&mut self, key: &CacheKey) -> Option<&CacheEntry> {
+        self.counter += 1;
+        let counter = self.counter;
+        let entry = self.entries.get_mut(key)?;
+        self.lru.remove(&entry.last_used);
+        self.lru.insert(counter, key.clone());
+        entry.last_used = counter;
+        Some(entry)
+    }
+
+    fn remove(&mut self, key: &CacheKey) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.lru.remove(&entry.last_used);
+            self.size -= entry_size(key, &entry.response);
+        }
+    }
+
+    /* Return the cached response for the query, if there is one and
+     * it has not expired. */
+    pub fn get(&mut self, query: &str, server: &str,
+               now: Instant) -> Option<Vec<u8>> {
+        let key = (normalise_query(query), server.to_lowercase());
+        match self.touch(&key) {
+            Some(entry) if entry.expires > now => Some(entry.response.clone()),
+            _ => None
+        }
+    }
+
+    /* Return the cached response for the query, regardless of whether
+     * it has expired, if stale entries may be served. */
+    pub fn get_stale(&mut self, query: &str,
+                     server: &str) -> Option<Vec<u8>> {
+        if !self.config.serve_stale {
+            return None;
+        }
+        let key = (normalise_query(query), server.to_lowercase());
+        self.touch(&key).map(|entry| entry.response.clone())
+    }
+
+    pub fn insert(&mut self, query: &str, server: &str,
+                  response: Vec<u8>, now: Instant) {
+        let ttl =
+            if is_negative(&response) {
+                self.config.negative_ttl
+            } else {
+                self.config.ttl
+            };
+        self.insert_with_ttl(query, server, response, now, ttl);
+    }
+
+    /* Insert a response that is known to be incomplete (e.g. because
+     * a server that was referred to could not be queried).  It is
+     * cached for the negative TTL, so that the query is retried
+     * soon. */
+    pub fn insert_partial(&mut self, query: &str, server: &str,
+                          response: Vec<u8>, now: Instant) {
+        let ttl = self.config.negative_ttl;
+        self.insert_with_ttl(query, server, response, now, ttl);
+    }
+
+    fn insert_with_ttl(&mut self, query: &str, server: &str,
+                       response: Vec<u8>, now: Instant, ttl: Duration) {
+        if !is_cacheable(&response) {
+            return;
+        }
+        let key = (normalise_query(query), server.to_lowercase());
+        let size = entry_size(&key, &response);
+        if size > self.config.max_size {
+            return;
+        }
+        self.remove(&key);
+        while self.size + size > self.config.max_size {
+            let oldest = match self.lru.iter().next() {
+                Some((_, key)) => key.clone(),
+                None           => break
+            };
+            self.remove(&oldest);
+        }
+
+        self.counter += 1;
+        self.lru.insert(self.counter, key.clone());
+        self.size += size;
+        self.entries.insert(key, CacheEntry {
+            response,
+            expires:   now + ttl,
+            last_used: self.counter,
+        });
+    }
+}
@@ -0,0 +1,88 @@
+extern crate futures;
+
+use super::cache::normalise_query;
+
+use self::futures::Future;
+use self::futures::future::Shared;
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io;
+use std::rc::Rc;
+
+/* Coalescing of identical upstream queries.  If a query to a server
+ * is made while an identical query to the same server is in
+ * progress, then the result of the earlier query is shared, rather
+ * than a new request being made.  Queries are identical if they are
+ * the same after normalisation (see cache.rs).  The error is shared
+ * too: each waiter receives an error with the same kind and message
+ * as the original. */
+
+pub type RequestFuture<T> = Box<dyn Future<Item = T, Error = io::Error>>;
+
+type RequestKey = (String, String);
+
+/* The requests that are currently in progress, keyed by (normalised
+ * query, server).  This is only for use on the reactor thread. */
+pub struct Coalescer<T> {
+    in_flight: Rc<RefCell<HashMap<RequestKey, Shared<RequestFuture<T>>>>>,
+}
+
+impl<T> Clone for Coalescer<T> {
+    fn clone(&self) -> Coalescer<T> {
+        Coalescer { in_flight: self.in_flight.clone() }
+    }
+}
+
+impl<T> Default for Coalescer<T> {
+    fn default() -> Coalescer<T> {
+        Coalescer { in_flight: Rc::new(RefCell::new(HashMap::new())) }
+    }
+}
+
+impl<T: Clone + 'static> Coalescer<T> {
+    pub fn new() -> Coalescer<T> {
+        Coalescer::default()
+    }
+
+    /* Return the result of the query to the server.  If an identical
+     * query is in progress, then its result is used, and otherwise
+     * 'request' is called to make a new request.  The request is
+     * removed from the in-progress set when it completes, whether it
+     * succeeds or fails. */
+    pub fn query<F>(&self, query: &str, server: &str,
+                    request: F) -> RequestFuture<T>
+            where F: FnOnce() -> RequestFuture<T> {
+        let key = (normalise_query(query), server.to_lowercase());
+        let existing = self.in_flight.borrow().get(&key).cloned();
+        let shared = match existing {
+            Some(shared) => {
+                info!("'{}' for {} already in progress, sharing result",
+                      query, server);
+                shared
+            },
+            None => {
+                let in_flight = self.in_flight.clone();
+                let key_ = key.clone();
+                let request: RequestFuture<T> = Box::new(
+                    request().then(move |result| {
+                        in_flight.borrow_mut().remove(&key_);
+                        result
+                    })
+                );
+                let shared = request.shared();
+                self.in_flight.borrow_mut().insert(key, shared.clone());
+                shared
+            }
+        };
+        Box::new(shared.map(|item| (*item).clone())
+                       .map_err(|e| io::Error::new(e.kind(), e.to_string())))
+    }
+
+    /* Return whether an identical query to the server is in
+     * progress. */
+    pub fn is_in_flight(&self, query: &str, server: &str) -> bool {
+        let key = (normalise_query(query), server.to_lowercase());
+        self.in_flight.borrow().contains_key(&key)
+    }
+}
@@ -0,0 +1,93 @@
+extern crate ipnet;
+
+use super::cache::CacheConfig;
+use super::listener::ListenAddress;
+use super::policy::{AccessList, QueryPolicy};
+use super::ratelimit::RateLimitConfig;
+use super::resolver::ResolverConfig;
+use super::tls::TlsConfig;
+use super::upstream::{Timeouts, UpstreamConfig};
+
+use self::ipnet::IpNet;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ReferralOutput {
+    /* Return only the response from the last server queried. */
+    Final,
+    /* Return the responses from each server queried, in order, with
+     * a separator line before each one. */
+    Chain,
+}
+
+#[derive(Clone)]
+pub struct Config {
+    pub default_server:  String,
+    pub port:            String,
+    /* The addresses to listen on.  If empty, then the server listens
+     * on 0.0.0.0, using the port above. */
+    pub listen:          Vec<ListenAddress>,
+    pub tls:             Option<TlsConfig>,
+    /* The addresses of proxies that send a PROXY protocol header at
+     * the start of each connection (empty if none do). */
+    pub proxy_protocol:  Vec<IpNet>,
+    pub max_referrals:   u32,
+    pub referral_output: ReferralOutput,
+    pub upstream:        UpstreamConfig,
+    pub timeouts:        Timeouts,
+    pub resolver:        ResolverConfig,
+    pub cache:           CacheConfig,
+    /* The default maximum number of concurrent connections to each
+     * upstream server (zero means no limit), and the time for which
+     * a query will wait for a connection when the limit is reached. */
+    pub max_connections: usize,
+    pub queue_timeout:   Duration,
+    pub rate_limit:      RateLimitConfig,
+    pub access_list:     Arc<AccessList>,
+    pub query_policy:    QueryPolicy,
+    /* The maximum length of a query line, and the time for which a
+     * client connection may be idle before the query is received. */
+    pub max_query_length: usize,
+    pub idle_timeout:     Duration,
+    /* The maximum number of queries from a single bulk request (see
+     * server.rs) that are sent upstream at the same time. */
+    pub bulk_concurrency: usize,
+    /* The maximum number of parts into which a query for a resource
+     * that is split across servers is divided (see server.rs), where
+     * zero means that such queries are not split. */
+    pub max_fan_out:      usize,
+    /* Whether IPv6 addresses with an embedded IPv4 address (e.g.
+     * IPv4-mapped and 6to4 addresses) are routed by the IPv4 address
+     * (see context.rs). */
+    pub embedded_ipv4:    bool,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            default_server:  "whois.iana.org".to_owned(),
+            port:            "4343".to_owned(),
+            listen:          Vec::new(),
+            tls:             None,
+            proxy_protocol:  Vec::new(),
+            max_referrals:   3,
+            referral_output: ReferralOutput::Final,
+            upstream:        UpstreamConfig::default(),
+            timeouts:        Timeouts::default(),
+            resolver:        ResolverConfig::default(),
+            cache:           CacheConfig::default(),
+            max_connections: 0,
+            queue_timeout:   Duration::from_secs(10),
+            rate_limit:      RateLimitConfig::default(),
+            access_list:     Arc::new(AccessList::default()),
+            query_policy:    QueryPolicy::default(),
+            max_query_length: 1024,
+            idle_timeout:     Duration::from_secs(30),
+            bulk_concurrency: 8,
+            max_fan_out:      8,
+            embedded_ipv4:    false,
+        }
+    }
+}
@@ -10,6 +10,9 @@ use super::lookup::AsnResourceLookup;
 use super::lookup::Ipv4ResourceLookup;
 use super::lookup::Ipv6ResourceLookup;
 use super::lookup::ResourceLookup;
+use super::lookup::range_to_prefixes;
+use super::idna::domain_to_ascii;
+use super::target::UpstreamTarget;
 
 use self::ipnet::Ipv4Net;
 use self::ipnet::Ipv6Net;
@@ -21,20 +24,325 @@ use std::fs::File;
 use std::iter::FromIterator;
 use std::net::Ipv4Addr;
 use std::net::Ipv6Addr;
+use std::path::Path;
 use std::str::FromStr;
 
+#[derive(Clone, PartialEq, Debug)]
+pub enum Resource {
+    Ipv4(Ipv4Net),
+    Ipv6(Ipv6Net),
+    Asn(AsnRange),
+    /* A domain name, in lowercase ASCII form (see idna.rs). */
+    Domain(String),
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ResourceFamily {
+    Ipv4,
+    Ipv6,
+    Asn,
+    Domain,
+    Unhandled,
+}
+
+impl Resource {
+    pub fn family(&self) -> ResourceFamily {
+        match *self {
+            Resource::Ipv4(_) => ResourceFamily::Ipv4,
+            Resource::Ipv6(_) => ResourceFamily::Ipv6,
+            Resource::Asn(_)  => ResourceFamily::Asn,
+            Resource::Domain(_) => ResourceFamily::Domain,
+        }
+    }
+}
+
+lazy_static! {
+    static ref ASN_REGEX: Regex =
+        Regex::new(r"^(?i)AS(\d+)$").unwrap();
+    static ref ASN_RANGE_REGEX: Regex =
+        Regex::new(r"^(?i)AS(\d+)\s*-AS(\d+)$").unwrap();
+    static ref IP_RANGE_REGEX: Regex =
+        Regex::new(r"^([0-9A-Fa-f:.]+)\s*-\s*([0-9A-Fa-f:.]+)$").unwrap();
+}
+
+/* Format the ASN range in the form used in queries. */
+pub fn format_asn_range(range: AsnRange) -> String {
+    let last = range.end.value.wrapping_sub(1);
+    match range.start.value == last {
+        true  => format!("AS{}", last),
+        false => format!("AS{}-AS{}", range.start.value, last)
+    }
+}
+
+/* Parse the query value as an IP address range in dash notation
+ * (e.g. '192.0.2.0 - 192.0.2.255'), and return the smallest set of
+ * prefixes that covers the range, in order. */
+pub fn parse_ip_range(value: &str) -> Option<Vec<Resource>> {
+    let captures = IP_RANGE_REGEX.captures(value.trim())?;
+    let start = captures.get(1).unwrap().as_str();
+    let end   = captures.get(2).unwrap().as_str();
+
+    if let (Ok(start), Ok(end)) = (Ipv4Addr::from_str(start),
+                                   Ipv4Addr::from_str(end)) {
+        let (first, last) = (u32::from(start) as u128, u32::from(end) as u128);
+        if first > last {
+            return None;
+        }
+        return Some(
+            range_to_prefixes(first, last, 32).into_iter()
+                .map(|(address, prefix_length)| {
+                    Resource::Ipv4(
+                        Ipv4Net::new(Ipv4Addr::from(address as u32),
+                                     prefix_length).unwrap()
+                    )
+                })
+                .collect()
+        );
+    }
+
+    if let (Ok(start), Ok(end)) = (Ipv6Addr::from_str(start),
+                                   Ipv6Addr::from_str(end)) {
+        let (first, last) = (u128::from(start), u128::from(end));
+        if first > last {
+            return None;
+        }
+        return Some(
+            range_to_prefixes(first, last, 128).into_iter()
+                .map(|(address, prefix_length)| {
+                    Resource::Ipv6(
+                        Ipv6Net::new(Ipv6Addr::from(address),
+                                     prefix_length).unwrap()
+                    )
+                })
+                .collect()
+        );
+    }
+
+    None
+}
+
+/* Parse the query value as a set of resources: the prefixes that make
+ * up an IP address range, or otherwise the single resource given by
+ * parse_resource. */
+pub fn parse_resources(value: &str) -> Option<Vec<Resource>> {
+    match parse_ip_range(value) {
+        Some(resources) => Some(resources),
+        None => parse_resource(value).map(|resource| vec![resource])
+    }
+}
+
+/* Parse the query value as an IP address, IP address prefix, ASN, ASN
+ * range, reverse DNS zone, or domain name. */
+pub fn parse_resource(value: &str) -> Option<Resource> {
+    let ipv4_address_result = Ipv4Addr::from_str(value);
+    if let Ok(ipv4_address) = ipv4_address_result {
+        let ipv4_net = Ipv4Net::new(ipv4_address, 32).unwrap();
+        return Some(Resource::Ipv4(ipv4_net));
+    }
+
+    let ipv4_net_result = Ipv4Net::from_str(value);
+    if let Ok(ipv4_net) = ipv4_net_result {
+        return Some(Resource::Ipv4(ipv4_net));
+    }
+
+    let ipv6_address_result = Ipv6Addr::from_str(value);
+    if let Ok(ipv6_address) = ipv6_address_result {
+        let ipv6_net = Ipv6Net::new(ipv6_address, 128).unwrap();
+        return Some(Resource::Ipv6(ipv6_net));
+    }
+
+    let ipv6_net_result = Ipv6Net::from_str(value);
+    if let Ok(ipv6_net) = ipv6_net_result {
+        return Some(Resource::Ipv6(ipv6_net));
+    }
+
+    if let Some(captures) = ASN_REGEX.captures(value) {
+        if let Ok(asn_value) = u32::from_str(captures.get(1).unwrap().as_str()) {
+            return Some(Resource::Asn(
+                AsnRange{ start: Asn { value: asn_value },
+                          end:   Asn { value: asn_value.wrapping_add(1) }}
+            ));
+        }
+    }
+
+    if let Some(captures) = ASN_RANGE_REGEX.captures(value) {
+        if let Ok(asn_value_start) = u32::from_str(captures.get(1).unwrap().as_str()) {
+            if let Ok(asn_value_end) = u32::from_str(captures.get(2).unwrap().as_str()) {
+                if asn_value_start > asn_value_end {
+                    return None;
+                }
+                /* The range end is exclusive, so it is zero if the
+                 * range includes the last ASN. */
+                return Some(Resource::Asn(
+                    AsnRange { start: Asn { value: asn_value_start },
+                               end:   Asn { value: asn_value_end.wrapping_add(1) } }
+                ));
+            }
+        }
+    }
+
+    parse_reverse_zone(value)
+        .or_else(|| parse_domain(value).map(Resource::Domain))
+}
+
+/* Parse the query value as a domain name, and return its lowercase
+ * ASCII form.  The name must have at least two labels, and the last
+ * label must not be numeric, so that handles and other single words
+ * are not treated as domain names. */
+pub fn parse_domain(value: &str) -> Option<String> {
+    let name = domain_to_ascii(value)?;
+    let labels: Vec<&str> = name.split('.').collect();
+    let is_valid_label = |label: &&str| {
+        label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+            && !label.starts_with('-') && !label.ends_with('-')
+    };
+    if labels.len() < 2
+            || !labels.iter().all(is_valid_label)
+            || labels.last().unwrap().chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    Some(name)
+}
+
+/* IPv6 prefixes that embed an IPv4 address: the prefix, its length,
+ * the position of the first bit of the IPv4 address, and the value
+ * with which the IPv4 address is XORed. */
+const EMBEDDED_IPV4_FORMATS: [(u128, u8, u8, u32); 4] = [
+    /* IPv4-mapped (::ffff:0:0/96). */
+    (0xffff_0000_0000, 96, 96, 0),
+    /* NAT64 well-known prefix (64:ff9b::/96, RFC 6052). */
+    (0x0064_ff9b << 96, 96, 96, 0),
+    /* 6to4 (2002::/16, RFC 3056). */
+    (0x2002 << 112, 16, 16, 0),
+    /* Teredo (2001::/32, RFC 4380), where the client's address is
+     * stored in the last 32 bits, with each bit inverted. */
+    (0x2001 << 112, 32, 96, 0xffff_ffff),
+];
+
+/* Return the IPv4 address or prefix embedded in the IPv6 address or
+ * prefix, for the formats in EMBEDDED_IPV4_FORMATS.  The IPv6 prefix
+ * must include the bits that precede the IPv4 address, and at least
+ * one bit of the IPv4 address. */
+pub fn embedded_ipv4_net(ipv6_net: Ipv6Net) -> Option<Ipv4Net> {
+    let address = u128::from(ipv6_net.addr());
+    let prefix_length = ipv6_net.prefix_len();
+    for &(format, format_length, start, xor) in EMBEDDED_IPV4_FORMATS.iter() {
+        let mask = !(u128::MAX >> format_length);
+        if address & mask != format || prefix_length <= start {
+            continue;
+        }
+        let end = start + 32;
+        let ipv4_address = ((address >> (128 - end as u32)) as u32) ^ xor;
+        let ipv4_length = prefix_length.min(end) - start;
+        return Ipv4Net::new(Ipv4Addr::from(ipv4_address), ipv4_length)
+            .ok().map(|net| net.trunc());
+    }
+    None
+}
+
+/* Parse an IPv4 reverse zone, given its labels in order from the most
+ * significant octet (e.g. ['192', '0', '2'] for 2.0.192.in-addr.arpa).
+ * A classless (RFC 2317) label of the form '<start>/<length>' or
+ * '<start>-<length>' is supported in place of the last octet. */
+fn parse_ipv4_reverse_zone(labels: &[&str]) -> Option<Ipv4Net> {
+    if labels.is_empty() || labels.len() > 4 {
+        return None;
+    }
+    let is_octet = |label: &str| {
+        !label.is_empty() && label.len() <= 3
+            && label.chars().all(|c| c.is_ascii_digit())
+    };
+    let mut octets = [0u8; 4];
+    let mut prefix_length = 0;
+    for (index, label) in labels.iter().enumerate() {
+        if is_octet(label) {
+            octets[index] = u8::from_str(label).ok()?;
+            prefix_length += 8;
+            continue;
+        }
+        if index != 3 {
+            return None;
+        }
+        let mut parts = label.splitn(2, ['/', '-']);
+        let start = parts.next().filter(|p| is_octet(p))?;
+        let length = parts.next().filter(|p| is_octet(p))?;
+        let start = u8::from_str(start).ok()?;
+        let length = u8::from_str(length).ok()?;
+        if !(25..=32).contains(&length)
+                || (start as u32) & ((1 << (32 - length)) - 1) != 0 {
+            return None;
+        }
+        octets[index] = start;
+        prefix_length = length;
+    }
+    Ipv4Net::new(Ipv4Addr::from(octets), prefix_length).ok()
+}
+
+/* Parse an IPv6 reverse zone, given its nibble labels in order from the
+ * most significant nibble. */
+fn parse_ipv6_reverse_zone(labels: &[&str]) -> Option<Ipv6Net> {
+    if labels.is_empty() || labels.len() > 32 {
+        return None;
+    }
+    let mut value: u128 = 0;
+    for label in labels {
+        if label.len() != 1 {
+            return None;
+        }
+        value = (value << 4) | u128::from_str_radix(label, 16).ok()?;
+    }
+    let bits = labels.len() * 4;
+    let address = match bits {
+        128 => value,
+        _   => value << (128 - bits)
+    };
+    Ipv6Net::new(Ipv6Addr::from(address), bits as u8).ok()
+}
+
+/* Parse the query value as a reverse DNS zone (e.g.
+ * '2.0.192.in-addr.arpa' or '8.b.d.0.1.0.0.2.ip6.arpa'), and return
+ * the equivalent IP address prefix. */
+pub fn parse_reverse_zone(value: &str) -> Option<Resource> {
+    let name = value.trim_end_matches('.').to_lowercase();
+    if let Some(labels) = name.strip_suffix(".in-addr.arpa") {
+        let labels: Vec<&str> = labels.split('.').rev().collect();
+        return parse_ipv4_reverse_zone(&labels).map(Resource::Ipv4);
+    }
+    if let Some(labels) = name.strip_suffix(".ip6.arpa") {
+        let labels: Vec<&str> = labels.split('.').rev().collect();
+        return parse_ipv6_reverse_zone(&labels).map(Resource::Ipv6);
+    }
+    None
+}
+
 pub struct Context {
     pub ipv4:    Ipv4ResourceLookup,
     pub ipv6:    Ipv6ResourceLookup,
     pub asn:     AsnResourceLookup,
+    /* The canonical form of each server (see target.rs), and the
+     * corresponding upstream target. */
     pub servers: Vec<String>,
+    pub targets: Vec<UpstreamTarget>,
+    /* The server for each domain suffix (usually a TLD), where the
+     * suffix is in lowercase ASCII form. */
+    pub domains: HashMap<String, u32>,
+    /* Whether IPv6 addresses with an embedded IPv4 address are routed
+     * by the IPv4 address (see embedded_ipv4_net). */
+    pub embedded_ipv4: bool,
 }
 
 impl Context {
+    /* The domain file is optional, since it was added after the
+     * others, and is treated as empty if it does not exist. */
     pub fn from_files(ipv4_file: &str,
                       ipv6_file: &str,
-                      asn_file: &str) -> Context {
-        let files: Vec<&str> = vec![ipv4_file,ipv6_file,asn_file];
+                      asn_file: &str,
+                      domain_file: &str) -> Context {
+        let mut files: Vec<&str> = vec![ipv4_file,ipv6_file,asn_file];
+        let has_domain_file = Path::new(domain_file).exists();
+        if has_domain_file {
+            files.push(domain_file);
+        }
         let mut servers: HashSet<String> = HashSet::new();
         files.iter().for_each(|s| {
             let file = File::open(s).unwrap();
@@ -48,13 +356,32 @@ impl Context {
                                 )); });
         });
 
-        let mut reverse_servers = Vec::from_iter(servers.drain());
+        /* Servers that are written differently but have the same
+         * canonical form share an index. */
+        let mut targets: HashMap<String, UpstreamTarget> = HashMap::new();
+        let mut raw_targets: HashMap<String, String> = HashMap::new();
+        servers.iter()
+            .filter(|s| !s.is_empty())
+            .for_each(|s| {
+                let target = UpstreamTarget::from_str(s).unwrap_or_else(|e| {
+                    panic!("invalid server '{}' in mapping data: {}", s, e)
+                });
+                let canonical = target.to_string();
+                raw_targets.insert(s.clone(), canonical.clone());
+                targets.insert(canonical, target);
+            });
+
+        let mut reverse_servers = Vec::from_iter(targets.keys().cloned());
         reverse_servers.sort();
 
-        let mut servers: HashMap<String, u32> = HashMap::new();
+        let mut indexes: HashMap<String, u32> = HashMap::new();
         reverse_servers.iter()
             .enumerate()
-            .for_each(|(i, s)| { servers.insert(s.clone(), i as u32); });
+            .for_each(|(i, s)| { indexes.insert(s.clone(), i as u32); });
+        let servers: HashMap<String, u32> =
+            raw_targets.iter()
+                .map(|(raw, canonical)| (raw.clone(), indexes[canonical]))
+                .collect();
 
         let file = File::open(ipv4_file).unwrap();
         let mut csv_reader = csv::ReaderBuilder::new()
@@ -114,16 +441,40 @@ impl Context {
                                             *(servers.get(server).unwrap()))
                             ); });
 
-        let mut final_servers = Vec::new();
-        for i in reverse_servers.iter() {
-            final_servers.push(i.clone());
+        let mut domain_entries = HashMap::new();
+        if has_domain_file {
+            let file = File::open(domain_file).unwrap();
+            let mut csv_reader = csv::ReaderBuilder::new()
+                .has_headers(false)
+                .from_reader(file);
+            csv_reader.records()
+                .filter_map(|i| i.ok())
+                .for_each(|i| { let name   = i.get(0).unwrap();
+                                let server = i.get(1).unwrap();
+                                if server.is_empty() {
+                                    return;
+                                }
+                                let suffix = domain_to_ascii(name).unwrap_or_else(|| {
+                                    panic!("invalid domain '{}' in mapping data", name)
+                                });
+                                domain_entries.insert(
+                                    suffix, *(servers.get(server).unwrap())
+                                ); });
         }
 
+        let final_targets =
+            reverse_servers.iter()
+                .map(|s| targets.remove(s).unwrap())
+                .collect();
+
         Context {
             ipv4:    ResourceLookup::from_iter(ipv4_entries),
             ipv6:    ResourceLookup::from_iter(ipv6_entries),
             asn:     ResourceLookup::from_iter(asn_entries),
-            servers: final_servers,
+            servers: reverse_servers,
+            targets: final_targets,
+            domains: domain_entries,
+            embedded_ipv4: false,
         }
     }
 
@@ -138,51 +489,119 @@ impl Context {
         }
     }
 
-    pub fn lookup_internal(&self, value: &str) -> Option<u32> {
-        let ipv4_address_result = Ipv4Addr::from_str(value);
-        if let Ok(ipv4_address) = ipv4_address_result {
-            let ipv4_net = Ipv4Net::new(ipv4_address, 32).unwrap();
-            return self.ipv4.get_longest_match_value(ipv4_net);
+    pub fn lookup_target(&self, value: &str) -> Option<&UpstreamTarget> {
+        match self.lookup_internal(value) {
+            Some(server_index) => {
+                self.targets.get(server_index as usize)
+            },
+            None => {
+                None
+            }
         }
+    }
 
-        let ipv4_net_result = Ipv4Net::from_str(value);
-        if let Ok(ipv4_net) = ipv4_net_result {
-            return self.ipv4.get_longest_match_value(ipv4_net);
+    /* Split the resource into parts, where each part is handled by a
+     * single server (or by no server), for resources that are not
+     * handled by a single server.  The result is empty if the value
+     * is not a resource, or if it would have to be split into too
+     * many parts. */
+    pub fn lookup_parts(&self, value: &str) -> Vec<(String, Option<&String>)> {
+        let mut parts: Vec<(String, Option<u32>)> = Vec::new();
+        for resource in parse_resources(value).unwrap_or_default() {
+            match self.resource_parts(&resource) {
+                Some(mut resource_parts) => parts.append(&mut resource_parts),
+                None => { return Vec::new(); }
+            }
         }
+        parts.into_iter()
+            .map(|(part, index)| {
+                (part, index.and_then(|i| self.servers.get(i as usize)))
+            })
+            .collect()
+    }
 
-        let ipv6_address_result = Ipv6Addr::from_str(value);
-        if let Ok(ipv6_address) = ipv6_address_result {
-            let ipv6_net = Ipv6Net::new(ipv6_address, 128).unwrap();
-            return self.ipv6.get_longest_match_value(ipv6_net);
+    /* Domain names are not split. */
+    fn resource_parts(&self, resource: &Resource)
+            -> Option<Vec<(String, Option<u32>)>> {
+        match *resource {
+            Resource::Ipv4(ipv4_net) => {
+                Some(self.ipv4.get_parts(ipv4_net)?
+                         .into_iter()
+                         .map(|(net, index)| (net.to_string(), index))
+                         .collect())
+            },
+            Resource::Ipv6(ipv6_net) => {
+                Some(self.ipv6.get_parts(ipv6_net)?
+                         .into_iter()
+                         .map(|(net, index)| (net.to_string(), index))
+                         .collect())
+            },
+            Resource::Asn(asn_range) => {
+                Some(self.asn.get_parts(asn_range)?
+                         .into_iter()
+                         .map(|(range, index)| (format_asn_range(range), index))
+                         .collect())
+            },
+            Resource::Domain(_) => None
         }
+    }
 
-        let ipv6_net_result = Ipv6Net::from_str(value);
-        if let Ok(ipv6_net) = ipv6_net_result {
-            return self.ipv6.get_longest_match_value(ipv6_net);
+    /* Return the IPv4 address or prefix embedded in the IPv6 address
+     * or prefix, if routing by embedded IPv4 addresses is enabled. */
+    pub fn embedded_ipv4(&self, value: &str) -> Option<Ipv4Net> {
+        if !self.embedded_ipv4 {
+            return None;
         }
+        match parse_resource(value) {
+            Some(Resource::Ipv6(ipv6_net)) => embedded_ipv4_net(ipv6_net),
+            _ => None
+        }
+    }
 
-        let asn_regex = Regex::new(r"^(?i)AS(\d+)$").unwrap();
-        if let Some(captures) = asn_regex.captures(value) {
-            if let Ok(asn_value) = u32::from_str(captures.get(1).unwrap().as_str()) {
-                return self.asn.get_longest_match_value(
-                    AsnRange{ start: Asn { value: asn_value as u32 },
-                              end:   Asn { value: (asn_value as u32) + 1 }}
-                );
-            }
+    /* An IP address range is only handled by a server if each of its
+     * prefixes is handled by that server. */
+    pub fn lookup_internal(&self, value: &str) -> Option<u32> {
+        if let Some(ipv4_net) = self.embedded_ipv4(value) {
+            return self.ipv4.get_longest_match_value(ipv4_net);
         }
+        let resources = parse_resources(value)?;
+        let first = self.lookup_resource(resources.first()?)?;
+        match resources.iter().skip(1)
+                       .all(|r| self.lookup_resource(r) == Some(first)) {
+            true  => Some(first),
+            false => None
+        }
+    }
 
-        let asn_range_regex = Regex::new(r"^(?i)AS(\d+)\s*-AS(\d+)$").unwrap();
-        if let Some(captures) = asn_range_regex.captures(value) {
-            if let Ok(asn_value_start) = u32::from_str(captures.get(1).unwrap().as_str()) {
-                if let Ok(asn_value_end) = u32::from_str(captures.get(2).unwrap().as_str()) {
-                    return self.asn.get_longest_match_value(
-                        AsnRange { start: Asn { value: asn_value_start as u32 },
-                                   end:   Asn { value: asn_value_end as u32 } }
-                    );
-                }
+    fn lookup_resource(&self, resource: &Resource) -> Option<u32> {
+        match *resource {
+            Resource::Ipv4(ipv4_net) => {
+                self.ipv4.get_longest_match_value(ipv4_net)
+            },
+            Resource::Ipv6(ipv6_net) => {
+                self.ipv6.get_longest_match_value(ipv6_net)
+            },
+            Resource::Asn(asn_range) => {
+                self.asn.get_longest_match_value(asn_range)
+            },
+            Resource::Domain(ref name) => {
+                self.lookup_domain(name)
             }
         }
+    }
 
-        return None;
+    /* Return the server for the longest suffix of the domain name
+     * that is in the mapping data. */
+    fn lookup_domain(&self, name: &str) -> Option<u32> {
+        let mut suffix = name;
+        loop {
+            if let Some(&index) = self.domains.get(suffix) {
+                return Some(index);
+            }
+            match suffix.find('.') {
+                Some(index) => { suffix = &suffix[index + 1..]; },
+                None        => { return None; }
+            }
+        }
     }
 }
@@ -1,6 +1,7 @@
 pub mod iana;
 pub mod delegated;
 pub mod processor;
+pub mod root_zone;
 
 extern crate csv;
 extern crate intervaltree;
@@ -18,6 +19,7 @@ use self::iana::Iana;
 use self::ipnet::Ipv4Net;
 use self::ipnet::Ipv6Net;
 use self::processor::Processor;
+use self::root_zone::parse_root_zone_data;
 
 use std::collections::HashMap;
 use std::fs::File;
@@ -84,10 +86,19 @@ fn run_processors(directory:  &str,
     });
 }
 
+fn write_domains(directory: &str, domain_path: &str) {
+    let mut output_file = File::create(domain_path).unwrap();
+    parse_root_zone_data(directory).iter().for_each(|(tld, server)| {
+        let line = format!("{},{}\n", tld, server);
+        output_file.write_all(line.as_bytes()).unwrap();
+    });
+}
+
 pub fn process_public(public_data_dir: &str,
                       ipv4_path: &str,
                       ipv6_path: &str,
-                      asn_path: &str) {
+                      asn_path: &str,
+                      domain_path: &str) {
     let mut servers: HashMap<String, u32> = HashMap::new();
     servers.insert(String::from_str("").unwrap(),                  0);
     servers.insert(String::from_str("whois.afrinic.net").unwrap(), 1);
@@ -100,4 +111,5 @@ pub fn process_public(public_data_dir: &str,
                    vec![Box::new(Iana::new()),
                         Box::new(Delegated::new())],
                    ipv4_path, ipv6_path, asn_path);
+    write_domains(public_data_dir, domain_path);
 }
@@ -0,0 +1,63 @@
+use super::super::idna::domain_to_ascii;
+
+use std::fs;
+use std::fs::File;
+use std::io::Read;
+
+/* The root zone data is a copy of the Whois response from
+ * whois.iana.org for each TLD, with one file per TLD in the
+ * 'iana/root-zone' directory (see get-root-zone-data).  Each response
+ * includes the TLD ('domain:') and the TLD's Whois server ('whois:'),
+ * if it has one. */
+
+/* Return the value of the first field with the given name in the
+ * Whois response. */
+fn field_value<'a>(response: &'a str, name: &str) -> Option<&'a str> {
+    response.lines()
+        .filter_map(|line| {
+            let mut parts = line.splitn(2, ':');
+            match (parts.next(), parts.next()) {
+                (Some(key), Some(value)) if key.trim() == name => {
+                    Some(value.trim())
+                },
+                _ => None
+            }
+        })
+        .next()
+}
+
+/* Return the TLD (in lowercase ASCII form) and its Whois server from
+ * the Whois response for the TLD, if the TLD has a Whois server. */
+pub fn parse_root_zone_whois(response: &str) -> Option<(String, String)> {
+    let tld = domain_to_ascii(field_value(response, "domain")?)?;
+    let server = field_value(response, "whois")?.to_lowercase();
+    if server.is_empty() {
+        return None;
+    }
+    Some((tld, server))
+}
+
+/* Return the TLD and Whois server pairs from the root zone data,
+ * sorted by TLD.  The root zone data is optional (see
+ * get-root-zone-data), so the result is empty if it is missing. */
+pub fn parse_root_zone_data(directory: &str) -> Vec<(String, String)> {
+    let path = format!("{}/iana/root-zone", directory);
+    let files = match fs::read_dir(&path) {
+        Ok(files) => files,
+        Err(e) => {
+            warn!("Unable to read root zone data from {}: {}", path, e);
+            return Vec::new();
+        }
+    };
+    let mut entries: Vec<(String, String)> =
+        files
+            .map(|entry| entry.unwrap().path())
+            .filter_map(|path| {
+                let mut data = Vec::new();
+                File::open(path).unwrap().read_to_end(&mut data).unwrap();
+                parse_root_zone_whois(&String::from_utf8_lossy(&data))
+            })
+            .collect();
+    entries.sort();
+    entries
+}
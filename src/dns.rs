@@ -0,0 +1,279 @@
+extern crate rand;
+
+use std::cmp;
+use std::fs::File;
+use std::io::{self, Read};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/* A minimal DNS stub resolver, used to resolve upstream server names
+ * along with the TTLs of their address records, since the system
+ * resolver does not expose the TTLs.  Queries for A and AAAA records
+ * are sent over UDP to the nameservers from resolv.conf, which are
+ * expected to be recursive resolvers.  Truncated responses and
+ * responses that cannot be parsed are treated as errors, so that the
+ * caller can fall back to the system resolver. */
+
+pub const RESOLV_CONF: &str = "/etc/resolv.conf";
+
+pub const TYPE_A:     u16 = 1;
+pub const TYPE_CNAME: u16 = 5;
+pub const TYPE_AAAA:  u16 = 28;
+
+const CLASS_IN:    u16   = 1;
+const HEADER_LEN:  usize = 12;
+const MAX_MESSAGE: usize = 512;
+
+/* The addresses from a response, and the lowest TTL of the records
+ * (addresses and aliases) in the answer section, if there are any. */
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Answer {
+    pub addresses: Vec<IpAddr>,
+    pub ttl:       Option<u32>,
+}
+
+impl Answer {
+    fn merge(&mut self, other: Answer) {
+        for address in other.addresses {
+            if !self.addresses.contains(&address) {
+                self.addresses.push(address);
+            }
+        }
+        self.ttl = match (self.ttl, other.ttl) {
+            (Some(a), Some(b)) => Some(cmp::min(a, b)),
+            (a, b)             => a.or(b)
+        };
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_owned())
+}
+
+/* Return the nameservers from the 'nameserver' lines of resolv.conf
+ * content.  Lines with addresses that cannot be parsed are
+ * ignored. */
+pub fn parse_nameservers(content: &str) -> Vec<SocketAddr> {
+    content.lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            match (fields.next(), fields.next()) {
+                (Some("nameserver"), Some(address)) => {
+                    /* Link-local IPv6 addresses may have a zone index,
+                     * which is not supported here. */
+                    IpAddr::from_str(address).ok()
+                        .map(|address| SocketAddr::new(address, 53))
+                },
+                _ => None
+            }
+        })
+        .collect()
+}
+
+/* Return the nameservers from the resolv.conf file at the given path,
+ * or no nameservers if the file cannot be read. */
+pub fn nameservers_from_file(path: &str) -> Vec<SocketAddr> {
+    let mut content = String::new();
+    match File::open(path).and_then(|mut f| f.read_to_string(&mut content)) {
+        Ok(_)  => parse_nameservers(&content),
+        Err(_) => Vec::new()
+    }
+}
+
+fn push_u16(buffer: &mut Vec<u8>, value: u16) {
+    buffer.push((value >> 8) as u8);
+    buffer.push(value as u8);
+}
+
+fn read_u16(packet: &[u8], offset: usize) -> io::Result<u16> {
+    match packet.get(offset..offset + 2) {
+        Some(bytes) => Ok(((bytes[0] as u16) << 8) | (bytes[1] as u16)),
+        None        => Err(invalid("response is truncated"))
+    }
+}
+
+fn read_u32(packet: &[u8], offset: usize) -> io::Result<u32> {
+    let high = read_u16(packet, offset)? as u32;
+    let low = read_u16(packet, offset + 2)? as u32;
+    Ok((high << 16) | low)
+}
+
+/* Return a recursive query for records of the given type for the
+ * name. */
+pub fn build_query(id: u16, name: &str, record_type: u16) -> io::Result<Vec<u8>> {
+    let name = name.trim_end_matches('.');
+    if name.is_empty() || name.len() > 253 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                  "invalid name length"));
+    }
+    let mut query = Vec::with_capacity(HEADER_LEN + name.len() + 6);
+    push_u16(&mut query, id);
+    /* Recursion desired. */
+    push_u16(&mut query, 0x0100);
+    for &count in &[1, 0, 0, 0] {
+        push_u16(&mut query, count);
+    }
+    for label in name.split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "invalid label length"));
+        }
+        query.push(label.len() as u8);
+        query.extend_from_slice(label.as_bytes());
+    }
+    query.push(0);
+    push_u16(&mut query, record_type);
+    push_u16(&mut query, CLASS_IN);
+    Ok(query)
+}
+
+/* Return the offset of the first byte after the (possibly compressed)
+ * name at the given offset. */
+fn skip_name(packet: &[u8], mut offset: usize) -> io::Result<usize> {
+    loop {
+        let length = match packet.get(offset) {
+            Some(&length) => length as usize,
+            None          => return Err(invalid("response is truncated"))
+        };
+        if length == 0 {
+            return Ok(offset + 1);
+        }
+        if length & 0xC0 == 0xC0 {
+            return Ok(offset + 2);
+        }
+        offset += 1 + length;
+    }
+}
+
+/* Parse the response to a query with the given ID for records of the
+ * given type.  A response indicating that the name does not exist
+ * has no addresses. */
+pub fn parse_response(id: u16, record_type: u16,
+                      packet: &[u8]) -> io::Result<Answer> {
+    if packet.len() < HEADER_LEN {
+        return Err(invalid("response is truncated"));
+    }
+    if read_u16(packet, 0)? != id {
+        return Err(invalid("response ID does not match query"));
+    }
+    let flags = read_u16(packet, 2)?;
+    if flags & 0x8000 == 0 {
+        return Err(invalid("message is not a response"));
+    }
+    if flags & 0x0200 != 0 {
+        return Err(invalid("response is truncated"));
+    }
+    match flags & 0x000F {
+        0 => {},
+        3 => return Ok(Answer::default()),
+        rcode => {
+            return Err(io::Error::other(format!("response code {}", rcode)));
+        }
+    }
+    let questions = read_u16(packet, 4)?;
+    let answers = read_u16(packet, 6)?;
+
+    let mut offset = HEADER_LEN;
+    for _ in 0..questions {
+        offset = skip_name(packet, offset)? + 4;
+    }
+    let mut answer = Answer::default();
+    for _ in 0..answers {
+        offset = skip_name(packet, offset)?;
+        let rr_type = read_u16(packet, offset)?;
+        let rr_class = read_u16(packet, offset + 2)?;
+        let ttl = read_u32(packet, offset + 4)?;
+        let length = read_u16(packet, offset + 8)? as usize;
+        let data = match packet.get(offset + 10..offset + 10 + length) {
+            Some(data) => data,
+            None       => return Err(invalid("response is truncated"))
+        };
+        offset += 10 + length;
+        if rr_class != CLASS_IN {
+            continue;
+        }
+        let address = match (rr_type, length) {
+            (TYPE_A, 4) if rr_type == record_type => {
+                let mut octets = [0; 4];
+                octets.copy_from_slice(data);
+                Some(IpAddr::V4(Ipv4Addr::from(octets)))
+            },
+            (TYPE_AAAA, 16) if rr_type == record_type => {
+                let mut octets = [0; 16];
+                octets.copy_from_slice(data);
+                Some(IpAddr::V6(Ipv6Addr::from(octets)))
+            },
+            (TYPE_CNAME, _) => None,
+            _ => continue
+        };
+        answer.merge(Answer { addresses: address.into_iter().collect(),
+                              ttl: Some(ttl) });
+    }
+    if answer.addresses.is_empty() {
+        /* The TTLs of aliases alone are not useful to the caller. */
+        answer.ttl = None;
+    }
+    Ok(answer)
+}
+
+/* Send a query for records of the given type for the name to the
+ * nameserver, and return the answer. */
+fn query(name: &str, record_type: u16, nameserver: SocketAddr,
+         timeout: Duration) -> io::Result<Answer> {
+    let id = rand::random::<u16>();
+    let message = build_query(id, name, record_type)?;
+    let local: SocketAddr =
+        if nameserver.is_ipv4() {
+            SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0)
+        } else {
+            SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), 0)
+        };
+    let socket = UdpSocket::bind(local)?;
+    socket.connect(nameserver)?;
+    socket.send(&message)?;
+
+    /* Packets that are not responses to this query are ignored. */
+    let deadline = Instant::now() + timeout;
+    let mut buffer = [0; MAX_MESSAGE];
+    loop {
+        let now = Instant::now();
+        if now >= deadline {
+            return Err(io::Error::new(io::ErrorKind::TimedOut,
+                                      "nameserver did not respond"));
+        }
+        socket.set_read_timeout(Some(deadline - now))?;
+        let size = match socket.recv(&mut buffer) {
+            Ok(size) => size,
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                return Err(io::Error::new(io::ErrorKind::TimedOut,
+                                          "nameserver did not respond"));
+            },
+            Err(e) => return Err(e)
+        };
+        if read_u16(&buffer[..size], 0).ok() != Some(id) {
+            continue;
+        }
+        return parse_response(id, record_type, &buffer[..size]);
+    }
+}
+
+/* Return the IPv4 and IPv6 addresses for the name.  The nameservers
+ * are tried in turn, until one of them answers both queries. */
+pub fn lookup(name: &str, nameservers: &[SocketAddr],
+              timeout: Duration) -> io::Result<Answer> {
+    let mut last_error =
+        io::Error::new(io::ErrorKind::NotFound, "no nameservers configured");
+    for &nameserver in nameservers {
+        let result =
+            query(name, TYPE_A, nameserver, timeout).and_then(|mut answer| {
+                answer.merge(query(name, TYPE_AAAA, nameserver, timeout)?);
+                Ok(answer)
+            });
+        match result {
+            Ok(answer) => return Ok(answer),
+            Err(e)     => { last_error = e; }
+        }
+    }
+    Err(last_error)
+}
@@ -0,0 +1,124 @@
+/* Conversion of internationalised domain names to their ASCII form
+ * (RFC 3490 and RFC 3492).  Each label that contains non-ASCII
+ * characters is converted to lowercase and then encoded as Punycode,
+ * with an 'xn--' prefix.  Unicode normalisation is not performed, so
+ * names should be in NFC form already (as is usually the case for
+ * names that have been typed or copied). */
+
+const BASE:         u32 = 36;
+const TMIN:         u32 = 1;
+const TMAX:         u32 = 26;
+const SKEW:         u32 = 38;
+const DAMP:         u32 = 700;
+const INITIAL_BIAS: u32 = 72;
+const INITIAL_N:    u32 = 128;
+
+/* The maximum length of a label and of a name, in ASCII form. */
+pub const MAX_LABEL_LENGTH: usize = 63;
+pub const MAX_NAME_LENGTH:  usize = 253;
+
+fn adapt(delta: u32, points: u32, first: bool) -> u32 {
+    let mut delta = if first { delta / DAMP } else { delta / 2 };
+    delta += delta / points;
+    let mut k = 0;
+    while delta > ((BASE - TMIN) * TMAX) / 2 {
+        delta /= BASE - TMIN;
+        k += BASE;
+    }
+    k + (BASE - TMIN + 1) * delta / (delta + SKEW)
+}
+
+fn encode_digit(digit: u32) -> char {
+    match digit {
+        0..=25 => (b'a' + digit as u8) as char,
+        _      => (b'0' + (digit - 26) as u8) as char
+    }
+}
+
+/* Encode the label as Punycode (without the 'xn--' prefix).  The
+ * result is None if the encoding overflows. */
+pub fn punycode_encode(label: &str) -> Option<String> {
+    let code_points: Vec<u32> = label.chars().map(|c| c as u32).collect();
+    let mut output: String = label.chars().filter(|c| c.is_ascii()).collect();
+    let basic_count = output.len() as u32;
+    if basic_count > 0 {
+        output.push('-');
+    }
+
+    let mut n = INITIAL_N;
+    let mut delta: u32 = 0;
+    let mut bias = INITIAL_BIAS;
+    let mut handled = basic_count;
+    while (handled as usize) < code_points.len() {
+        let m = *code_points.iter().filter(|&&c| c >= n).min()?;
+        delta = delta.checked_add((m - n).checked_mul(handled + 1)?)?;
+        n = m;
+        for &c in code_points.iter() {
+            if c < n {
+                delta = delta.checked_add(1)?;
+            }
+            if c == n {
+                let mut q = delta;
+                let mut k = BASE;
+                loop {
+                    let t =
+                        if k <= bias {
+                            TMIN
+                        } else if k >= bias + TMAX {
+                            TMAX
+                        } else {
+                            k - bias
+                        };
+                    if q < t {
+                        break;
+                    }
+                    output.push(encode_digit(t + (q - t) % (BASE - t)));
+                    q = (q - t) / (BASE - t);
+                    k += BASE;
+                }
+                output.push(encode_digit(q));
+                bias = adapt(delta, handled + 1, handled == basic_count);
+                delta = 0;
+                handled += 1;
+            }
+        }
+        delta = delta.checked_add(1)?;
+        n += 1;
+    }
+    Some(output)
+}
+
+/* Convert the domain name to lowercase ASCII form.  A trailing dot is
+ * removed.  The result is None if the name has an empty label, or if
+ * a label or the name is too long. */
+pub fn domain_to_ascii(name: &str) -> Option<String> {
+    /* The ideographic full stop and its variants are also label
+     * separators. */
+    let name: String =
+        name.chars()
+            .map(|c| match c {
+                '\u{3002}' | '\u{ff0e}' | '\u{ff61}' => '.',
+                _ => c
+            })
+            .collect();
+    let name = name.strip_suffix('.').unwrap_or(&name);
+
+    let mut labels = Vec::new();
+    for label in name.split('.') {
+        let label = label.to_lowercase();
+        let ascii_label =
+            match label.is_ascii() {
+                true  => label,
+                false => format!("xn--{}", punycode_encode(&label)?)
+            };
+        if ascii_label.is_empty() || ascii_label.len() > MAX_LABEL_LENGTH {
+            return None;
+        }
+        labels.push(ascii_label);
+    }
+    let ascii_name = labels.join(".");
+    match ascii_name.len() > MAX_NAME_LENGTH {
+        true  => None,
+        false => Some(ascii_name)
+    }
+}
@@ -0,0 +1,146 @@
+extern crate futures;
+extern crate tokio_io;
+
+use self::futures::{Async, Poll, Stream};
+use self::tokio_io::AsyncRead;
+
+use std::io;
+
+/* Reads query lines from a client connection.  Unlike
+ * tokio_io::io::lines, the length of each line is bounded, and lines
+ * containing control characters (including CR or LF characters other
+ * than those at the end of the line) are reported as invalid, so that
+ * they are not passed through to upstream servers. */
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Input {
+    /* A query line, without the line terminator. */
+    Query(String),
+    /* A line that is longer than the maximum length.  The remainder
+     * of the line is discarded. */
+    TooLong,
+    /* A line that contains control characters or that is not valid
+     * UTF-8. */
+    Invalid,
+}
+
+/* The handling of the first line from a client, where that line does
+ * not start a session. */
+#[derive(Clone, Debug, PartialEq)]
+pub enum FirstLine {
+    /* The line is a query to be handled. */
+    Query(String),
+    /* The query is refused for the given reason: the message is sent
+     * to the client, and the connection is closed. */
+    Refused { reason: &'static str, message: &'static str },
+    /* The client closed the connection without sending a line, so
+     * the connection is closed without a reply. */
+    Closed,
+}
+
+pub fn first_line(input: Option<Input>) -> FirstLine {
+    match input {
+        Some(Input::Query(ref line)) if line.trim().is_empty() => {
+            FirstLine::Refused { reason:  "empty query",
+                                 message: "% Empty query\n" }
+        },
+        Some(Input::Query(line)) => FirstLine::Query(line),
+        Some(Input::TooLong) => {
+            FirstLine::Refused { reason:  "query too long",
+                                 message: "% Query too long\n" }
+        },
+        Some(Input::Invalid) => {
+            FirstLine::Refused { reason:  "invalid characters",
+                                 message: "% Query contains invalid characters\n" }
+        },
+        None => FirstLine::Closed
+    }
+}
+
+const READ_SIZE: usize = 1024;
+
+pub struct QueryLines<R> {
+    reader:     R,
+    buffer:     Vec<u8>,
+    max_length: usize,
+    discarding: bool,
+    eof:        bool,
+}
+
+/* Return a stream of the lines read from the reader.  The stream ends
+ * when the reader reaches EOF.  A line may be terminated by LF or
+ * CRLF, and the final line need not be terminated at all. */
+pub fn query_lines<R: AsyncRead>(reader: R, max_length: usize) -> QueryLines<R> {
+    QueryLines {
+        reader,
+        buffer:     Vec::new(),
+        max_length,
+        discarding: false,
+        eof:        false,
+    }
+}
+
+impl<R> QueryLines<R> {
+    fn parse_line(&self, line: &[u8]) -> Input {
+        let line =
+            if line.ends_with(b"\r") {
+                &line[..line.len() - 1]
+            } else {
+                line
+            };
+        if line.len() > self.max_length {
+            return Input::TooLong;
+        }
+        if line.iter().any(|&c| (c < 0x20 && c != b'\t') || c == 0x7f) {
+            return Input::Invalid;
+        }
+        match String::from_utf8(line.to_vec()) {
+            Ok(line) => Input::Query(line),
+            Err(_)   => Input::Invalid
+        }
+    }
+}
+
+impl<R: AsyncRead> Stream for QueryLines<R> {
+    type Item = Input;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Option<Input>, io::Error> {
+        loop {
+            if let Some(index) = self.buffer.iter().position(|&c| c == b'\n') {
+                let line: Vec<u8> = self.buffer.drain(..=index).collect();
+                if self.discarding {
+                    self.discarding = false;
+                    continue;
+                }
+                return Ok(Async::Ready(Some(self.parse_line(&line[..index]))));
+            }
+            if self.discarding {
+                self.buffer.clear();
+            } else if self.buffer.len() > self.max_length + 1 {
+                /* The extra byte allows for a trailing CR. */
+                self.buffer.clear();
+                self.discarding = true;
+                return Ok(Async::Ready(Some(Input::TooLong)));
+            }
+            if self.eof {
+                if self.buffer.is_empty() {
+                    return Ok(Async::Ready(None));
+                }
+                let line: Vec<u8> = self.buffer.drain(..).collect();
+                return Ok(Async::Ready(Some(self.parse_line(&line))));
+            }
+
+            let mut data = [0; READ_SIZE];
+            match self.reader.read(&mut data) {
+                Ok(0) => { self.eof = true; },
+                Ok(count) => self.buffer.extend_from_slice(&data[..count]),
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    return Ok(Async::NotReady);
+                },
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {},
+                Err(e) => return Err(e)
+            }
+        }
+    }
+}
@@ -3,7 +3,27 @@ extern crate log;
 #[macro_use]
 extern crate lazy_static;
 
+pub mod cache;
+pub mod coalesce;
+pub mod config;
 pub mod context;
 pub mod data;
+pub mod dns;
+pub mod idna;
+pub mod input;
+pub mod limiter;
+pub mod listener;
 pub mod lookup;
+pub mod policy;
+pub mod pool;
+pub mod proxy;
+pub mod query;
+pub mod ratelimit;
+pub mod referral;
+pub mod resolver;
+pub mod rwhois;
 pub mod server;
+pub mod session;
+pub mod target;
+pub mod tls;
+pub mod upstream;
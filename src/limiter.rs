@@ -0,0 +1,187 @@
+extern crate futures;
+
+use self::futures::{Future, Poll, Async};
+use self::futures::unsync::oneshot;
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/* Limits the number of concurrent connections to each upstream
+ * server.  Requests beyond the limit are queued, and are given a
+ * permit (in order) as earlier permits are released.  This is only
+ * for use on the reactor thread. */
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LimiterStats {
+    /* The number of permits currently held. */
+    pub active:          usize,
+    /* The number of requests currently waiting for a permit. */
+    pub queue_depth:     usize,
+    /* The largest queue depth seen. */
+    pub max_queue_depth: usize,
+    /* The number of requests that had to wait for a permit. */
+    pub waited:          u64,
+    /* The total time spent waiting by those requests. */
+    pub total_wait:      Duration,
+}
+
+struct ServerState {
+    waiters: VecDeque<oneshot::Sender<Permit>>,
+    stats:   LimiterStats,
+}
+
+#[derive(Clone, Default)]
+pub struct Limiter {
+    servers: Rc<RefCell<HashMap<String, ServerState>>>,
+}
+
+/* A permit for a connection to an upstream server.  The permit is
+ * released when it is dropped. */
+pub struct Permit {
+    limiter: Option<Limiter>,
+    server:  String,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        if let Some(limiter) = self.limiter.take() {
+            limiter.release(&self.server);
+        }
+    }
+}
+
+pub struct Acquire {
+    receiver: Option<oneshot::Receiver<Permit>>,
+    permit:   Option<Permit>,
+    limiter:  Limiter,
+    server:   String,
+    start:    Instant,
+}
+
+impl Future for Acquire {
+    type Item = Permit;
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Permit, ()> {
+        if let Some(permit) = self.permit.take() {
+            return Ok(Async::Ready(permit));
+        }
+        let permit = match self.receiver.as_mut().unwrap().poll() {
+            Ok(Async::Ready(permit)) => permit,
+            Ok(Async::NotReady)      => return Ok(Async::NotReady),
+            Err(_)                   => return Err(())
+        };
+        self.receiver = None;
+        let wait = self.start.elapsed();
+        {
+            let mut servers = self.limiter.servers.borrow_mut();
+            let state = servers.get_mut(&self.server).unwrap();
+            state.stats.waited += 1;
+            state.stats.total_wait += wait;
+        }
+        info!("Acquired connection to {} after waiting {}ms",
+               self.server, wait.as_millis());
+        Ok(Async::Ready(permit))
+    }
+}
+
+impl Drop for Acquire {
+    fn drop(&mut self) {
+        /* If the request is abandoned while queued, then it no longer
+         * counts towards the queue depth. */
+        if let Some(receiver) = self.receiver.take() {
+            drop(receiver);
+            let mut servers = self.limiter.servers.borrow_mut();
+            if let Some(state) = servers.get_mut(&self.server) {
+                state.waiters.retain(|waiter| !waiter.is_canceled());
+                state.stats.queue_depth = state.waiters.len();
+            }
+        }
+    }
+}
+
+impl Limiter {
+    pub fn new() -> Limiter {
+        Limiter::default()
+    }
+
+    /* Request a permit for a connection to the given server.  If
+     * the limit is zero, then there is no limit. */
+    pub fn acquire(&self, server: &str, limit: usize) -> Acquire {
+        let server = server.to_lowercase();
+        let mut servers = self.servers.borrow_mut();
+        let state = servers.entry(server.clone()).or_insert_with(|| {
+            ServerState { waiters: VecDeque::new(),
+                          stats:   LimiterStats::default() }
+        });
+
+        let mut acquire = Acquire {
+            receiver: None,
+            permit:   None,
+            limiter:  self.clone(),
+            server:   server.clone(),
+            start:    Instant::now(),
+        };
+        if limit == 0 || state.stats.active < limit {
+            state.stats.active += 1;
+            acquire.permit = Some(Permit { limiter: Some(self.clone()),
+                                           server });
+        } else {
+            let (sender, receiver) = oneshot::channel();
+            state.waiters.push_back(sender);
+            state.stats.queue_depth = state.waiters.len();
+            if state.stats.queue_depth > state.stats.max_queue_depth {
+                state.stats.max_queue_depth = state.stats.queue_depth;
+            }
+            info!("Connection limit reached for {}, queueing request \
+                   (queue depth {})",
+                  server, state.stats.queue_depth);
+            acquire.receiver = Some(receiver);
+        }
+        acquire
+    }
+
+    fn release(&self, server: &str) {
+        let mut servers = self.servers.borrow_mut();
+        let state = match servers.get_mut(server) {
+            Some(state) => state,
+            None        => return
+        };
+        /* Pass the permit on to the first waiter that is still
+         * interested in it. */
+        while let Some(waiter) = state.waiters.pop_front() {
+            state.stats.queue_depth = state.waiters.len();
+            if waiter.is_canceled() {
+                continue;
+            }
+            let permit = Permit { limiter: Some(self.clone()),
+                                  server:  server.to_owned() };
+            match waiter.send(permit) {
+                Ok(_)           => return,
+                Err(mut permit) => { permit.limiter = None; }
+            }
+        }
+        state.stats.active -= 1;
+    }
+
+    pub fn stats(&self, server: &str) -> LimiterStats {
+        match self.servers.borrow().get(&server.to_lowercase()) {
+            Some(state) => state.stats,
+            None        => LimiterStats::default()
+        }
+    }
+
+    /* Return the statistics for each server for which a permit has
+     * been requested, sorted by server. */
+    pub fn all_stats(&self) -> Vec<(String, LimiterStats)> {
+        let mut stats: Vec<(String, LimiterStats)> =
+            self.servers.borrow().iter()
+                .map(|(server, state)| (server.clone(), state.stats))
+                .collect();
+        stats.sort_by(|a, b| a.0.cmp(&b.0));
+        stats
+    }
+}
@@ -0,0 +1,155 @@
+extern crate futures;
+extern crate net2;
+extern crate tokio_core;
+extern crate tokio_io;
+extern crate tokio_uds;
+
+use self::futures::Stream;
+use self::net2::TcpBuilder;
+use self::tokio_core::net::TcpListener;
+use self::tokio_core::reactor::Handle;
+use self::tokio_io::{AsyncRead, AsyncWrite};
+use self::tokio_uds::UnixListener;
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::os::unix::fs::FileTypeExt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+const LISTEN_BACKLOG: i32 = 1024;
+
+/* An address that the server listens on for client connections. */
+#[derive(Clone, Debug, PartialEq)]
+pub enum ListenAddress {
+    /* A TCP address, such as 0.0.0.0:43 or [::]:43. */
+    Tcp(SocketAddr),
+    /* The path to a Unix domain socket, written as unix:/path. */
+    Unix(PathBuf),
+}
+
+impl FromStr for ListenAddress {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<ListenAddress, String> {
+        if let Some(path) = value.strip_prefix("unix:") {
+            if path.is_empty() {
+                return Err(format!("invalid listen address: {}", value));
+            }
+            return Ok(ListenAddress::Unix(PathBuf::from(path)));
+        }
+        SocketAddr::from_str(value)
+            .map(ListenAddress::Tcp)
+            .map_err(|_| format!("invalid listen address: {}", value))
+    }
+}
+
+impl fmt::Display for ListenAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ListenAddress::Tcp(ref address) => write!(f, "{}", address),
+            ListenAddress::Unix(ref path)   => write!(f, "unix:{}", path.display())
+        }
+    }
+}
+
+/* A connection to a client or server, of any type. */
+pub trait Connection: AsyncRead + AsyncWrite {}
+
+impl<T: AsyncRead + AsyncWrite> Connection for T {}
+
+/* The client connections accepted by a listener, with the client
+ * address for each.  Connections on Unix domain sockets are treated
+ * as coming from 127.0.0.1. */
+pub type Incoming =
+    Box<dyn Stream<Item = (Box<dyn Connection>, SocketAddr), Error = io::Error>>;
+
+pub struct Listener {
+    /* The address that the listener is bound to. */
+    pub address:  ListenAddress,
+    pub incoming: Incoming,
+}
+
+/* Convert an IPv4-mapped IPv6 address (as seen on a dual-stack
+ * listener) into the corresponding IPv4 address, so that IPv4 clients
+ * are treated the same way regardless of the listener. */
+fn unmap_address(address: SocketAddr) -> SocketAddr {
+    match address {
+        SocketAddr::V6(ref v6) => {
+            match v6.ip().to_ipv4_mapped() {
+                Some(v4) => SocketAddr::new(IpAddr::V4(v4), v6.port()),
+                None     => address
+            }
+        },
+        _ => address
+    }
+}
+
+fn bind_tcp(address: SocketAddr, only_v6: bool,
+            handle: &Handle) -> io::Result<Listener> {
+    let builder = match address {
+        SocketAddr::V4(_) => TcpBuilder::new_v4()?,
+        SocketAddr::V6(_) => {
+            let builder = TcpBuilder::new_v6()?;
+            builder.only_v6(only_v6)?;
+            builder
+        }
+    };
+    builder.reuse_address(true)?;
+    let std_listener = builder.bind(address)?.listen(LISTEN_BACKLOG)?;
+    let local_address = std_listener.local_addr()?;
+    let listener = TcpListener::from_listener(std_listener, &local_address,
+                                              handle)?;
+    let incoming =
+        listener.incoming().map(|(client, client_addr)| {
+            (Box::new(client) as Box<dyn Connection>,
+             unmap_address(client_addr))
+        });
+    Ok(Listener { address:  ListenAddress::Tcp(local_address),
+                  incoming: Box::new(incoming) })
+}
+
+fn bind_unix(path: &PathBuf, handle: &Handle) -> io::Result<Listener> {
+    /* Remove the socket left behind by a previous instance of the
+     * server, if there is one. */
+    if let Ok(metadata) = fs::symlink_metadata(path) {
+        if metadata.file_type().is_socket() {
+            fs::remove_file(path)?;
+        }
+    }
+    let listener = UnixListener::bind(path, handle)?;
+    let local_address =
+        SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 0);
+    let incoming =
+        listener.incoming().map(move |(client, _)| {
+            (Box::new(client) as Box<dyn Connection>, local_address)
+        });
+    Ok(Listener { address:  ListenAddress::Unix(path.clone()),
+                  incoming: Box::new(incoming) })
+}
+
+/* Bind to each of the addresses.  An IPv6 wildcard address accepts
+ * IPv4 connections as well (dual-stack), unless an IPv4 address with
+ * the same port is also being bound. */
+pub fn bind(addresses: &[ListenAddress],
+            handle: &Handle) -> io::Result<Vec<Listener>> {
+    addresses.iter().map(|address| {
+        match *address {
+            ListenAddress::Tcp(socket_addr) => {
+                let only_v6 =
+                    addresses.iter().any(|other| {
+                        match *other {
+                            ListenAddress::Tcp(SocketAddr::V4(ref v4)) => {
+                                v4.port() == socket_addr.port()
+                            },
+                            _ => false
+                        }
+                    });
+                bind_tcp(socket_addr, only_v6, handle)
+            },
+            ListenAddress::Unix(ref path) => bind_unix(path, handle)
+        }
+    }).collect()
+}
@@ -23,6 +23,102 @@ pub trait ResourceLookup<K, V> {
         where I: IntoIterator<Item = (K, V)>;
     fn get_longest_match(&self, value: K) -> Option<(Option<K>, V)>;
     fn get_longest_match_value(&self, value: K) -> Option<V>;
+    /* Split the value into parts, where each part has the value of
+     * the most specific entry that covers it (or None, if no entry
+     * covers it).  The result is None if more than MAX_PART_ENTRIES
+     * entries overlap with the value. */
+    fn get_parts(&self, value: K) -> Option<Vec<(K, Option<V>)>>;
+}
+
+/* The maximum number of entries that will be considered when
+ * splitting a value into parts, so as to bound the work done for
+ * very large values. */
+pub const MAX_PART_ENTRIES: usize = 1024;
+
+/* Split the inclusive range into parts, as for get_parts.  'entries'
+ * contains the inclusive ranges and values of the entries that
+ * overlap with the range.  Adjacent parts with the same value are
+ * merged. */
+fn split_range(first: u128, last: u128, entries: &[(u128, u128, u32)])
+        -> Vec<(u128, u128, Option<u32>)> {
+    let mut starts = vec![first];
+    for &(entry_first, entry_last, _) in entries {
+        if entry_first > first {
+            starts.push(entry_first);
+        }
+        if entry_last < last {
+            starts.push(entry_last + 1);
+        }
+    }
+    starts.sort();
+    starts.dedup();
+
+    let mut parts: Vec<(u128, u128, Option<u32>)> = Vec::new();
+    for (index, &start) in starts.iter().enumerate() {
+        let end =
+            match starts.get(index + 1) {
+                Some(next) => next - 1,
+                None       => last
+            };
+        let value =
+            entries.iter()
+                .filter(|&&(f, l, _)| f <= start && l >= end)
+                .min_by_key(|&&(f, l, _)| l - f)
+                .map(|&(_, _, value)| value);
+        match parts.last_mut() {
+            Some(part) if part.2 == value => { part.1 = end; },
+            _ => { parts.push((start, end, value)); }
+        }
+    }
+    parts
+}
+
+/* Return the prefixes (as address and prefix length pairs) that make
+ * up the inclusive range, for an address family with the given
+ * number of bits. */
+pub fn range_to_prefixes(first: u128, last: u128, bits: u32) -> Vec<(u128, u8)> {
+    /* Return the last address in the block of the given size. */
+    let block_last = |start: u128, size_bits: u32| {
+        match size_bits {
+            128 => u128::MAX,
+            _   => start + ((1u128 << size_bits) - 1)
+        }
+    };
+    let mut prefixes = Vec::new();
+    let mut start = first;
+    loop {
+        let mut size_bits =
+            if start == 0 { bits } else { start.trailing_zeros().min(bits) };
+        while size_bits > 0 && block_last(start, size_bits) > last {
+            size_bits -= 1;
+        }
+        prefixes.push((start, (bits - size_bits) as u8));
+        let end = block_last(start, size_bits);
+        if end >= last {
+            break;
+        }
+        start = end + 1;
+    }
+    prefixes
+}
+
+/* Return the entries that overlap with the inclusive range, as
+ * inclusive ranges, or None if there are too many of them.  The
+ * entries are only read until the limit is exceeded, since this runs
+ * on the reactor thread. */
+fn overlapping_entries<I>(entries: I, first: u128, last: u128)
+        -> Option<Vec<(u128, u128, u32)>>
+        where I: Iterator<Item = (u128, u128, u32)> {
+    let mut overlapping: Vec<(u128, u128, u32)> =
+        entries.filter(|&(f, l, _)| f <= last && l >= first)
+               .take(MAX_PART_ENTRIES + 1)
+               .collect();
+    if overlapping.len() > MAX_PART_ENTRIES {
+        return None;
+    }
+    overlapping.sort();
+    overlapping.dedup();
+    Some(overlapping)
 }
 
 fn to_u32(address: Ipv4Addr) -> u32 {
@@ -87,8 +183,10 @@ impl ResourceLookup<Ipv4Net, u32>
                 .collect();
         response.append(&mut matching_last_values);
         response.sort_by(
-            |a, b| { let a_size = ipv4_range_size(a.0.start, a.0.end);
-                     let b_size = ipv4_range_size(b.0.start, b.0.end);
+            |a, b| { let a_size = ipv4_range_size(a.0.start,
+                                                  ipv4_decrement(a.0.end));
+                     let b_size = ipv4_range_size(b.0.start,
+                                                  ipv4_decrement(b.0.end));
                      a_size.cmp(&b_size) }
         );
 
@@ -98,7 +196,13 @@ impl ResourceLookup<Ipv4Net, u32>
                 let range = &entry.0;
 
                 let host_count = ipv4_range_size(range.start, range.end);
-                let prefix_length: u32 = 32 - ((host_count as f32).log2() as u32);
+                /* A host count of zero means that the range covers the
+                 * entire address space. */
+                let prefix_length: u32 =
+                    match host_count {
+                        0 => 0,
+                        _ => 32 - ((host_count as f32).log2() as u32)
+                    };
 
                 Some((Some(Ipv4Net::new(range.start, prefix_length as u8).unwrap()),
                      entry.1))
@@ -115,6 +219,42 @@ impl ResourceLookup<Ipv4Net, u32>
         }
     }
 
+    fn get_parts(&self, net: Ipv4Net)
+            -> Option<Vec<(Ipv4Net, Option<u32>)>> {
+        let first = to_u32(net.addr()) as u128;
+        let last = to_u32(net.broadcast()) as u128;
+        /* Entries that include the last address are in last_values,
+         * so the query need not include that address. */
+        let range_end =
+            match ipv4_increment(net.broadcast()) {
+                end if end == ipv4_min_addr() => Ipv4Addr::from(u32::MAX),
+                end => end
+            };
+        let iter = IntervalTree::query(&self.interval_tree,
+                                       Range { start: net.addr(),
+                                               end:   range_end });
+        let entries =
+            iter.map(|i| { (i.range.start, i.range.end, i.value) })
+                .chain(self.last_values.iter()
+                           .map(|i| { (i.0.start, i.0.end, i.1) }))
+                .map(|(start, end, value)| {
+                    (to_u32(start) as u128,
+                     to_u32(ipv4_decrement(end)) as u128, value)
+                });
+        let entries = overlapping_entries(entries, first, last)?;
+
+        Some(split_range(first, last, &entries).into_iter()
+                 .flat_map(|(f, l, value)| {
+                     range_to_prefixes(f, l, 32).into_iter()
+                         .map(move |(address, prefix_length)| {
+                             (Ipv4Net::new(Ipv4Addr::from(address as u32),
+                                           prefix_length).unwrap(),
+                              value)
+                         })
+                 })
+                 .collect())
+    }
+
     fn from_iter<I: IntoIterator<Item=(Ipv4Net, u32)>>(values: I)
             -> Ipv4IntervalTree  {
         let interval_tree: IntervalTree<Ipv4Addr, u32> =
@@ -229,6 +369,42 @@ impl ResourceLookup<Ipv6Net, u32>
         }
     }
 
+    fn get_parts(&self, net: Ipv6Net)
+            -> Option<Vec<(Ipv6Net, Option<u32>)>> {
+        let first = u128::from(net.addr());
+        let last = u128::from(net.broadcast());
+        /* Entries that include the last address are in last_values,
+         * so the query need not include that address. */
+        let range_end =
+            match ipv6_increment(net.broadcast()) {
+                end if end == ipv6_min_addr() => Ipv6Addr::from(u128::MAX),
+                end => end
+            };
+        let iter = IntervalTree::query(&self.interval_tree,
+                                       Range { start: net.addr(),
+                                               end:   range_end });
+        let entries =
+            iter.map(|i| { (i.range.start, i.range.end, i.value) })
+                .chain(self.last_values.iter()
+                           .map(|i| { (i.0.start, i.0.end, i.1) }))
+                .map(|(start, end, value)| {
+                    (u128::from(start), u128::from(ipv6_decrement(end)),
+                     value)
+                });
+        let entries = overlapping_entries(entries, first, last)?;
+
+        Some(split_range(first, last, &entries).into_iter()
+                 .flat_map(|(f, l, value)| {
+                     range_to_prefixes(f, l, 128).into_iter()
+                         .map(move |(address, prefix_length)| {
+                             (Ipv6Net::new(Ipv6Addr::from(address),
+                                           prefix_length).unwrap(),
+                              value)
+                         })
+                 })
+                 .collect())
+    }
+
     fn from_iter<I: IntoIterator<Item=(Ipv6Net, u32)>>(values: I)
             -> Ipv6IntervalTree {
         let interval_tree: IntervalTree<Ipv6Addr, u32> =
@@ -286,13 +462,23 @@ impl ResourceLookup<AsnRange, u32>
     fn get_longest_match(&self, asrange: AsnRange)
             -> Option<(Option<AsnRange>, u32)> {
         let tree = &self.interval_tree;
-        let difference = (asrange.end.value - 1) - asrange.start.value;
+        let last = asrange.end.value.wrapping_sub(1);
+        if last < asrange.start.value {
+            return None;
+        }
+        /* Entries that include the last ASN are in last_values, so
+         * the query need not include that ASN. */
+        let range_end =
+            match asrange.end.value {
+                0   => Asn { value: u32::MAX },
+                end => Asn { value: end }
+            };
         let iter =
-            match difference == 0 {
+            match last == asrange.start.value {
                 true  => IntervalTree::query_point(tree, asrange.start),
                 false => IntervalTree::query(tree, Range {
                                                     start: asrange.start,
-                                                    end: asrange.end
+                                                    end: range_end
                                                    })
             };
 
@@ -310,8 +496,10 @@ impl ResourceLookup<AsnRange, u32>
                 .map(|i| { i.clone() })
                 .collect();
         response.append(&mut matching_last_values);
-        response.sort_by(|a, b| { let a_diff = a.0.end - a.0.start;
-                                  let b_diff = b.0.end - b.0.start;
+        /* Compare the inclusive sizes, since an entry that includes
+         * the last ASN has an end of zero. */
+        response.sort_by(|a, b| { let a_diff = a.0.end.value.wrapping_sub(1) - a.0.start.value;
+                                  let b_diff = b.0.end.value.wrapping_sub(1) - b.0.start.value;
                                   a_diff.cmp(&b_diff) });
 
         match response.len() >= 1 {
@@ -329,6 +517,42 @@ impl ResourceLookup<AsnRange, u32>
         }
     }
 
+    fn get_parts(&self, asrange: AsnRange)
+            -> Option<Vec<(AsnRange, Option<u32>)>> {
+        let first = asrange.start.value as u128;
+        let last = asrange.end.value.wrapping_sub(1) as u128;
+        if last < first {
+            return Some(Vec::new());
+        }
+        /* Entries that include the last ASN are in last_values, so
+         * the query need not include that ASN. */
+        let range_end =
+            match asrange.end.value {
+                0   => Asn { value: u32::MAX },
+                end => Asn { value: end }
+            };
+        let iter = IntervalTree::query(&self.interval_tree,
+                                       Range { start: asrange.start,
+                                               end:   range_end });
+        let entries =
+            iter.map(|i| { (i.range.start, i.range.end, i.value) })
+                .chain(self.last_values.iter()
+                           .map(|i| { (i.0.start, i.0.end, i.1) }))
+                .map(|(start, end, value)| {
+                    (start.value as u128,
+                     end.value.wrapping_sub(1) as u128, value)
+                });
+        let entries = overlapping_entries(entries, first, last)?;
+
+        Some(split_range(first, last, &entries).into_iter()
+                 .map(|(f, l, value)| {
+                     (AsnRange { start: Asn { value: f as u32 },
+                                 end:   Asn { value: (l as u32).wrapping_add(1) } },
+                      value)
+                 })
+                 .collect())
+    }
+
     fn from_iter<I: IntoIterator<Item=(AsnRange, u32)>>(values: I)
             -> AsnIntervalTree {
         let interval_tree: IntervalTree<Asn, u32> =
@@ -0,0 +1,254 @@
+extern crate csv;
+extern crate ipnet;
+
+use super::context::{Resource, ResourceFamily};
+use super::lookup::Ipv4ResourceLookup;
+use super::lookup::Ipv6ResourceLookup;
+use super::lookup::ResourceLookup;
+
+use self::ipnet::{Contains, IpNet, Ipv4Net, Ipv6Net};
+
+use std::fs::File;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+/* Client access control and query policy.
+ *
+ * The access control list is a CSV file where each line contains a
+ * client prefix and either 'allow' or 'deny'.  The most specific
+ * prefix containing the client's address determines whether the
+ * client may use the server, and clients that are not covered by any
+ * prefix are allowed:
+ *
+ *     0.0.0.0/0,deny
+ *     192.0.2.0/24,allow
+ *
+ * The query policy is a CSV file where each line contains an action
+ * ('allow' or 'deny') followed by the conditions for the rule, as
+ * key=value pairs.  The first rule whose conditions all match the
+ * query determines whether the query is permitted, and queries that
+ * do not match any rule are allowed:
+ *
+ *     deny,family=unhandled
+ *     deny,family=ipv4,prefix-length-below=8
+ *     deny,family=asn,asn-count-above=1000
+ *     allow,client=192.0.2.0/24
+ *     deny,upstream=whois.example.net
+ *
+ * The supported conditions are 'client' (a prefix containing the
+ * client's address), 'family' (ipv4, ipv6, asn, domain or unhandled),
+ * 'upstream' (the server the query would be sent to),
+ * 'prefix-length-below' and 'asn-count-above'.  A query is unhandled
+ * if it is not for a resource, or if the resource is not covered by
+ * the mapping data, since in either case it is sent to the default
+ * server.  (An unhandled query for a resource also matches that
+ * resource's family.) */
+
+const ACL_DENY:  u32 = 0;
+const ACL_ALLOW: u32 = 1;
+
+fn parse_action(value: &str) -> bool {
+    match value {
+        "allow" => true,
+        "deny"  => false,
+        _       => panic!("invalid policy action: {}", value)
+    }
+}
+
+pub struct AccessList {
+    ipv4: Ipv4ResourceLookup,
+    ipv6: Ipv6ResourceLookup,
+}
+
+impl Default for AccessList {
+    fn default() -> AccessList {
+        AccessList {
+            ipv4: ResourceLookup::from_iter(vec![]),
+            ipv6: ResourceLookup::from_iter(vec![]),
+        }
+    }
+}
+
+impl AccessList {
+    pub fn from_file(path: &str) -> AccessList {
+        let file = File::open(path).unwrap();
+        let mut csv_reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .from_reader(file);
+        let mut ipv4_entries = Vec::new();
+        let mut ipv6_entries = Vec::new();
+        csv_reader.records()
+            .map(|i| i.unwrap())
+            .for_each(|i| {
+                let prefix = i.get(0).unwrap();
+                let value =
+                    if parse_action(i.get(1).unwrap()) {
+                        ACL_ALLOW
+                    } else {
+                        ACL_DENY
+                    };
+                match IpNet::from_str(prefix).unwrap() {
+                    IpNet::V4(net) => ipv4_entries.push((net, value)),
+                    IpNet::V6(net) => ipv6_entries.push((net, value)),
+                }
+            });
+        AccessList {
+            ipv4: ResourceLookup::from_iter(ipv4_entries),
+            ipv6: ResourceLookup::from_iter(ipv6_entries),
+        }
+    }
+
+    pub fn is_allowed(&self, address: IpAddr) -> bool {
+        let value = match address {
+            IpAddr::V4(ipv4_address) => {
+                self.ipv4.get_longest_match_value(
+                    Ipv4Net::new(ipv4_address, 32).unwrap()
+                )
+            },
+            IpAddr::V6(ipv6_address) => {
+                self.ipv6.get_longest_match_value(
+                    Ipv6Net::new(ipv6_address, 128).unwrap()
+                )
+            }
+        };
+        value != Some(ACL_DENY)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct PolicyRule {
+    allow:               bool,
+    client:              Option<IpNet>,
+    family:              Option<ResourceFamily>,
+    upstream:            Option<String>,
+    prefix_length_below: Option<u8>,
+    asn_count_above:     Option<u64>,
+}
+
+fn parse_family(value: &str) -> ResourceFamily {
+    match value {
+        "ipv4"      => ResourceFamily::Ipv4,
+        "ipv6"      => ResourceFamily::Ipv6,
+        "asn"       => ResourceFamily::Asn,
+        "domain"    => ResourceFamily::Domain,
+        "unhandled" => ResourceFamily::Unhandled,
+        _           => panic!("invalid resource family: {}", value)
+    }
+}
+
+impl PolicyRule {
+    fn matches(&self, client: IpAddr, resource: Option<&Resource>,
+               upstream: &str, handled: bool) -> bool {
+        if let Some(ref net) = self.client {
+            if !net.contains(&client) {
+                return false;
+            }
+        }
+        if let Some(family) = self.family {
+            let matched = match (family, resource) {
+                (ResourceFamily::Unhandled, _) => resource.is_none() || !handled,
+                (_, Some(resource))            => family == resource.family(),
+                (_, None)                      => false
+            };
+            if !matched {
+                return false;
+            }
+        }
+        if let Some(ref server) = self.upstream {
+            if !server.eq_ignore_ascii_case(upstream) {
+                return false;
+            }
+        }
+        if let Some(length) = self.prefix_length_below {
+            let prefix_length = match resource {
+                Some(Resource::Ipv4(net)) => net.prefix_len(),
+                Some(Resource::Ipv6(net)) => net.prefix_len(),
+                _                         => return false
+            };
+            if prefix_length >= length {
+                return false;
+            }
+        }
+        if let Some(count) = self.asn_count_above {
+            let asn_count = match resource {
+                Some(Resource::Asn(range)) => {
+                    (range.end.value as u64)
+                        .wrapping_sub(range.start.value as u64)
+                        & 0xFFFFFFFF
+                },
+                _ => return false
+            };
+            if asn_count <= count {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct QueryPolicy {
+    rules: Vec<PolicyRule>,
+}
+
+impl QueryPolicy {
+    pub fn from_file(path: &str) -> QueryPolicy {
+        let file = File::open(path).unwrap();
+        let mut csv_reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(file);
+        let rules =
+            csv_reader.records()
+                .map(|i| i.unwrap())
+                .map(|i| {
+                    let mut rule = PolicyRule {
+                        allow: parse_action(i.get(0).unwrap()),
+                        ..PolicyRule::default()
+                    };
+                    for condition in i.iter().skip(1) {
+                        let mut parts = condition.splitn(2, '=');
+                        let key = parts.next().unwrap().trim();
+                        let value = parts.next().unwrap_or("").trim();
+                        match key {
+                            "client" => {
+                                rule.client = Some(IpNet::from_str(value).unwrap())
+                            },
+                            "family" => {
+                                rule.family = Some(parse_family(value))
+                            },
+                            "upstream" => {
+                                rule.upstream = Some(value.to_owned())
+                            },
+                            "prefix-length-below" => {
+                                rule.prefix_length_below =
+                                    Some(u8::from_str(value).unwrap())
+                            },
+                            "asn-count-above" => {
+                                rule.asn_count_above =
+                                    Some(u64::from_str(value).unwrap())
+                            },
+                            _ => panic!("invalid policy condition: {}", key)
+                        }
+                    }
+                    rule
+                })
+                .collect();
+        QueryPolicy { rules }
+    }
+
+    /* Return whether the query is permitted.  The resource is the
+     * resource parsed from the query (if any), the upstream is the
+     * server that the query would be sent to, and 'handled' is
+     * whether that server was found in the mapping data (rather than
+     * being the default server). */
+    pub fn is_allowed(&self, client: IpAddr, resource: Option<Resource>,
+                      upstream: &str, handled: bool) -> bool {
+        self.rules.iter()
+            .find(|rule| {
+                rule.matches(client, resource.as_ref(), upstream, handled)
+            })
+            .map(|rule| rule.allow)
+            .unwrap_or(true)
+    }
+}
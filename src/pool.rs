@@ -0,0 +1,131 @@
+extern crate futures;
+extern crate tokio_io;
+
+use self::futures::Future;
+use self::futures::future::{self, loop_fn, Either, Loop};
+use self::tokio_io::AsyncRead;
+use self::tokio_io::io::read;
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/* Pools of persistent connections to upstream servers.  Servers
+ * derived from the RIPE database software support the '-k' flag,
+ * which keeps the connection open after the response, so that
+ * further queries can be sent on the same connection.  The first
+ * query on a new connection is prefixed with '-k', and each response
+ * ends with two empty lines.  Once a response has been received, the
+ * connection is returned to the pool for the server, and the next
+ * query for that server takes it from the pool rather than making a
+ * new connection.  Each connection is used for one query at a time. */
+
+/* The default maximum number of idle connections to each server. */
+pub const DEFAULT_POOL_SIZE: usize = 4;
+
+/* The time after which an idle connection is no longer used.
+ * Servers close idle persistent connections eventually, so reusing
+ * old connections is likely to fail. */
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+type ResponseFuture<C> = Box<dyn Future<Item = (Option<C>, Vec<u8>),
+                                        Error = io::Error>>;
+
+/* Idle connections, along with the time at which each was returned to
+ * the pool. */
+type IdleConnections<C> = Vec<(C, Instant)>;
+
+/* The idle connections to each server.  This is only for use on the
+ * reactor thread. */
+pub struct SessionPool<C> {
+    idle: Rc<RefCell<HashMap<String, IdleConnections<C>>>>,
+}
+
+impl<C> Clone for SessionPool<C> {
+    fn clone(&self) -> SessionPool<C> {
+        SessionPool { idle: self.idle.clone() }
+    }
+}
+
+impl<C> Default for SessionPool<C> {
+    fn default() -> SessionPool<C> {
+        SessionPool { idle: Rc::new(RefCell::new(HashMap::new())) }
+    }
+}
+
+impl<C> SessionPool<C> {
+    pub fn new() -> SessionPool<C> {
+        SessionPool::default()
+    }
+
+    /* Take the most recently used idle connection to the server from
+     * the pool, if there is one.  Connections that have been idle for
+     * too long are closed. */
+    pub fn take(&self, server: &str, now: Instant) -> Option<C> {
+        let mut idle = self.idle.borrow_mut();
+        let connections = idle.get_mut(server)?;
+        connections.retain(|&(_, since)| now.duration_since(since) < IDLE_TIMEOUT);
+        connections.pop().map(|(connection, _)| connection)
+    }
+
+    /* Return a connection to the pool.  If the pool already has the
+     * maximum number of idle connections to the server, then the
+     * connection is closed instead. */
+    pub fn put(&self, server: &str, connection: C, max_idle: usize,
+               now: Instant) {
+        let mut idle = self.idle.borrow_mut();
+        let connections = idle.entry(server.to_owned()).or_default();
+        if connections.len() < max_idle {
+            connections.push((connection, now));
+        }
+    }
+
+    /* Return the number of idle connections to the server. */
+    pub fn idle_count(&self, server: &str) -> usize {
+        self.idle.borrow().get(server).map(|c| c.len()).unwrap_or(0)
+    }
+}
+
+/* Return whether the data is a complete response, i.e. whether it
+ * ends with two empty lines. */
+pub fn is_complete_response(data: &[u8]) -> bool {
+    let newlines =
+        data.iter().rev()
+            .take_while(|&&b| b == b'\n' || b == b'\r')
+            .filter(|&&b| b == b'\n')
+            .count();
+    newlines >= 3
+}
+
+/* Read the rest of a response on a persistent connection, where
+ * 'buffer' is the part of the response that has already been read.
+ * The connection is returned along with the response if the response
+ * was complete.  If the server closes the connection after sending
+ * some data, then that data is returned as the response, but the
+ * connection is not. */
+pub fn read_response<C>(connection: C, buffer: Vec<u8>) -> ResponseFuture<C>
+        where C: AsyncRead + 'static {
+    Box::new(loop_fn((connection, buffer), |(connection, mut buffer)| {
+        if is_complete_response(&buffer) {
+            return Either::A(future::ok(Loop::Break((Some(connection),
+                                                     buffer))));
+        }
+        Either::B(read(connection, vec![0; 4096]).and_then(
+            move |(connection, data, n)| {
+                if n > 0 {
+                    buffer.extend_from_slice(&data[..n]);
+                    return Ok(Loop::Continue((connection, buffer)));
+                }
+                if buffer.is_empty() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "connection closed without a response"
+                    ));
+                }
+                Ok(Loop::Break((None, buffer)))
+            }
+        ))
+    }))
+}
@@ -0,0 +1,182 @@
+extern crate futures;
+extern crate ipnet;
+extern crate tokio_io;
+
+use self::futures::{Async, Future, Poll};
+use self::ipnet::{Contains, IpNet};
+use self::tokio_io::AsyncRead;
+
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::str::FromStr;
+
+/* Support for the HAProxy PROXY protocol (versions 1 and 2), which is
+ * used by load balancers to pass the original client address to the
+ * server.  The header is only read from connections that come from a
+ * trusted address, and it is required on those connections.  See
+ * https://www.haproxy.org/download/2.0/doc/proxy-protocol.txt. */
+
+const V1_PREFIX: &[u8] = b"PROXY ";
+const V1_MAX_LENGTH: usize = 107;
+const V2_SIGNATURE: &[u8] = b"\r\n\r\n\x00\r\nQUIT\n";
+const V2_HEADER_LENGTH: usize = 16;
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData,
+                   format!("invalid PROXY header: {}", message))
+}
+
+/* Return whether the client address is one from which PROXY headers
+ * are accepted. */
+pub fn is_trusted(trusted: &[IpNet], address: IpAddr) -> bool {
+    trusted.iter().any(|net| net.contains(&address))
+}
+
+/* Return the total length of the header at the start of the data, if
+ * enough of the header has been read to determine it. */
+fn header_length(data: &[u8]) -> io::Result<Option<usize>> {
+    let prefix_length = data.len().min(V2_SIGNATURE.len());
+    if data[..prefix_length] == V2_SIGNATURE[..prefix_length] {
+        if data.len() < V2_HEADER_LENGTH {
+            return Ok(None);
+        }
+        let length = ((data[14] as usize) << 8) | (data[15] as usize);
+        return Ok(Some(V2_HEADER_LENGTH + length));
+    }
+    let prefix_length = data.len().min(V1_PREFIX.len());
+    if data[..prefix_length] == V1_PREFIX[..prefix_length] {
+        if let Some(index) = data.windows(2).position(|w| w == b"\r\n") {
+            return Ok(Some(index + 2));
+        }
+        if data.len() >= V1_MAX_LENGTH {
+            return Err(invalid("line too long"));
+        }
+        return Ok(None);
+    }
+    Err(invalid("missing header"))
+}
+
+fn parse_v1(header: &[u8]) -> io::Result<Option<SocketAddr>> {
+    let line = String::from_utf8(header[..header.len() - 2].to_vec())
+        .map_err(|_| invalid("not ASCII"))?;
+    let fields: Vec<&str> = line.split(' ').collect();
+    match fields.get(1) {
+        Some(&"UNKNOWN") => return Ok(None),
+        Some(&"TCP4") | Some(&"TCP6") => {},
+        _ => return Err(invalid("unknown protocol"))
+    }
+    if fields.len() != 6 {
+        return Err(invalid("wrong number of fields"));
+    }
+    let address = IpAddr::from_str(fields[2])
+        .map_err(|_| invalid("bad source address"))?;
+    let port = u16::from_str(fields[4])
+        .map_err(|_| invalid("bad source port"))?;
+    match (fields[1], address) {
+        ("TCP4", IpAddr::V4(_)) | ("TCP6", IpAddr::V6(_)) => {
+            Ok(Some(SocketAddr::new(address, port)))
+        },
+        _ => Err(invalid("address does not match protocol"))
+    }
+}
+
+fn parse_v2(header: &[u8]) -> io::Result<Option<SocketAddr>> {
+    let version_command = header[12];
+    if version_command >> 4 != 2 {
+        return Err(invalid("unsupported version"));
+    }
+    match version_command & 0x0F {
+        /* LOCAL: the connection was made by the proxy itself. */
+        0 => return Ok(None),
+        1 => {},
+        _ => return Err(invalid("unsupported command"))
+    }
+    let addresses = &header[V2_HEADER_LENGTH..];
+    let port = |offset: usize| {
+        ((addresses[offset] as u16) << 8) | (addresses[offset + 1] as u16)
+    };
+    match header[13] >> 4 {
+        /* AF_INET */
+        1 => {
+            if addresses.len() < 12 {
+                return Err(invalid("address block too short"));
+            }
+            let address = Ipv4Addr::new(addresses[0], addresses[1],
+                                        addresses[2], addresses[3]);
+            Ok(Some(SocketAddr::new(IpAddr::V4(address), port(8))))
+        },
+        /* AF_INET6 */
+        2 => {
+            if addresses.len() < 36 {
+                return Err(invalid("address block too short"));
+            }
+            let mut octets = [0; 16];
+            octets.copy_from_slice(&addresses[..16]);
+            let address = Ipv6Addr::from(octets);
+            Ok(Some(SocketAddr::new(IpAddr::V6(address), port(32))))
+        },
+        /* AF_UNSPEC, AF_UNIX */
+        _ => Ok(None)
+    }
+}
+
+/* Parse a complete PROXY header, returning the client address from
+ * the header.  The result is None if the header does not include a
+ * client address (e.g. for health checks made by the proxy itself). */
+pub fn parse_header(header: &[u8]) -> io::Result<Option<SocketAddr>> {
+    if header.starts_with(V2_SIGNATURE) {
+        parse_v2(header)
+    } else {
+        parse_v1(header)
+    }
+}
+
+pub struct ReadProxyHeader<S> {
+    stream: Option<S>,
+    buffer: Vec<u8>,
+}
+
+/* Read the PROXY header from the start of the stream.  Only the
+ * header is read, so the stream can then be used as normal. */
+pub fn read_proxy_header<S: AsyncRead>(stream: S) -> ReadProxyHeader<S> {
+    ReadProxyHeader { stream: Some(stream), buffer: Vec::new() }
+}
+
+impl<S: AsyncRead> Future for ReadProxyHeader<S> {
+    type Item = (S, Option<SocketAddr>);
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<(S, Option<SocketAddr>), io::Error> {
+        loop {
+            let length =
+                if self.buffer.is_empty() {
+                    None
+                } else {
+                    header_length(&self.buffer)?
+                };
+            if length == Some(self.buffer.len()) {
+                let address = parse_header(&self.buffer)?;
+                let stream = self.stream.take().unwrap();
+                return Ok(Async::Ready((stream, address)));
+            }
+            /* Until the header length is known, the header is read
+             * one byte at a time, so that no data following the
+             * header is consumed. */
+            let wanted = length.map(|l| l - self.buffer.len()).unwrap_or(1);
+            let mut data = vec![0; wanted];
+            let stream = self.stream.as_mut().unwrap();
+            match stream.read(&mut data) {
+                Ok(0) => {
+                    return Err(io::Error::new(io::ErrorKind::UnexpectedEof,
+                                              "connection closed before PROXY header"));
+                },
+                Ok(count) => self.buffer.extend_from_slice(&data[..count]),
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    return Ok(Async::NotReady);
+                },
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {},
+                Err(e) => return Err(e)
+            }
+        }
+    }
+}
@@ -0,0 +1,174 @@
+/* Query parsing.  Clients may include registry-specific flags and
+ * keywords in their queries (e.g. '-B 192.0.2.1' for the
+ * RIPE-database-derived servers, or 'n + 192.0.2.1' for ARIN).  These
+ * are separated from the resource so that the resource can be used
+ * for routing, and the query is then translated for the server that
+ * it is sent to. */
+
+/* Flags that take an argument, for the RIPE-database-derived servers
+ * (RIPE, APNIC and AFRINIC). */
+const RIPE_ARGUMENT_FLAGS: [&str; 7] = ["-i", "-T", "-s", "-t", "-v", "-q", "-V"];
+
+const RIPE_LONG_ARGUMENT_FLAGS: [&str; 8] = [
+    "--inverse", "--select-types", "--sources", "--template",
+    "--verbose", "--client", "--types", "--valid-syntax",
+];
+
+/* Keywords that may precede the resource in an ARIN query. */
+const ARIN_KEYWORDS: [&str; 11] = [
+    "n", "a", "net", "asn", "+", "-", "=", "!", "*", "<", ">"
+];
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum QueryStyle {
+    /* RIPE, APNIC and AFRINIC: RIPE-style flags are supported. */
+    Ripe,
+    /* ARIN: ARIN keywords are supported. */
+    Arin,
+    /* Other servers: only the resource is sent. */
+    Plain,
+}
+
+pub fn query_style(server: &str) -> QueryStyle {
+    match server.to_lowercase().as_str() {
+        "whois.ripe.net"
+      | "whois.apnic.net"
+      | "whois.afrinic.net" => QueryStyle::Ripe,
+        "whois.arin.net"    => QueryStyle::Arin,
+        _                   => QueryStyle::Plain
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct QueryFlag {
+    pub name:  String,
+    pub value: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Query {
+    pub original:      String,
+    pub flags:         Vec<QueryFlag>,
+    pub arin_keywords: Vec<String>,
+    pub resource:      String,
+}
+
+fn is_flag(token: &str) -> bool {
+    (token.len() > 1
+        && token.starts_with('-')
+        && token.chars().nth(1).unwrap().is_ascii_alphabetic())
+    || (token.len() > 2 && token.starts_with("--"))
+}
+
+impl Query {
+    pub fn parse(line: &str) -> Query {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let mut flags = Vec::new();
+        let mut arin_keywords = Vec::new();
+        let mut index = 0;
+
+        /* ARIN keywords only occur at the start of the query, and are
+         * not mixed with RIPE-style flags. */
+        while index + 1 < tokens.len()
+                && ARIN_KEYWORDS.contains(&tokens[index]) {
+            arin_keywords.push(tokens[index].to_owned());
+            index += 1;
+        }
+
+        if arin_keywords.is_empty() {
+            while index < tokens.len() && is_flag(tokens[index]) {
+                let name = tokens[index];
+                let mut parts = name.splitn(2, '=');
+                let name = parts.next().unwrap();
+                let inline_value = parts.next();
+                index += 1;
+                let value =
+                    if inline_value.is_some() {
+                        inline_value.map(|v| v.to_owned())
+                    } else if (RIPE_ARGUMENT_FLAGS.contains(&name)
+                                || RIPE_LONG_ARGUMENT_FLAGS.contains(&name))
+                            && index < tokens.len() {
+                        index += 1;
+                        Some(tokens[index - 1].to_owned())
+                    } else {
+                        None
+                    };
+                flags.push(QueryFlag { name: name.to_owned(), value });
+            }
+        }
+
+        let mut resource = tokens[index..].join(" ");
+        /* ARIN's ASN keyword takes a bare number. */
+        if arin_keywords.iter().any(|k| k == "a" || k == "asn")
+                && !resource.is_empty()
+                && resource.chars().all(|c| c.is_ascii_digit()) {
+            resource = format!("AS{}", resource);
+        }
+
+        Query {
+            original:      line.to_owned(),
+            flags,
+            arin_keywords,
+            resource,
+        }
+    }
+
+    /* Return the same query for a different resource. */
+    pub fn with_resource(&self, resource: &str) -> Query {
+        let mut parts = self.arin_keywords.clone();
+        parts.append(&mut self.ripe_flags());
+        parts.push(resource.to_owned());
+        Query {
+            original:      parts.join(" "),
+            flags:         self.flags.clone(),
+            arin_keywords: self.arin_keywords.clone(),
+            resource:      resource.to_owned(),
+        }
+    }
+
+    pub fn has_options(&self) -> bool {
+        !self.flags.is_empty() || !self.arin_keywords.is_empty()
+    }
+
+    fn ripe_flags(&self) -> Vec<String> {
+        self.flags.iter().map(|flag| {
+            match flag.value {
+                Some(ref value) => format!("{} {}", flag.name, value),
+                None            => flag.name.clone()
+            }
+        }).collect()
+    }
+
+    /* Return the query to send to a server with the given query
+     * style.  Options that the server would not understand are
+     * dropped.  If the query has no options, then it is returned
+     * unchanged. */
+    pub fn translate(&self, style: QueryStyle) -> String {
+        if !self.has_options() {
+            return self.original.clone();
+        }
+        let mut parts: Vec<String> = Vec::new();
+        match style {
+            QueryStyle::Ripe => {
+                parts.append(&mut self.ripe_flags());
+            },
+            QueryStyle::Arin => {
+                parts.append(&mut self.arin_keywords.clone());
+            },
+            QueryStyle::Plain => {}
+        }
+        if !self.resource.is_empty() {
+            if style == QueryStyle::Arin && !self.arin_keywords.is_empty() {
+                /* Use the original (numeric) form of the ASN. */
+                let original_resource: Vec<&str> =
+                    self.original.split_whitespace()
+                        .skip(self.arin_keywords.len())
+                        .collect();
+                parts.push(original_resource.join(" "));
+            } else {
+                parts.push(self.resource.clone());
+            }
+        }
+        parts.join(" ")
+    }
+}
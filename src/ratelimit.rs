@@ -0,0 +1,221 @@
+extern crate ipnet;
+
+use self::ipnet::{Ipv4Net, Ipv6Net};
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+/* Per-client rate limiting.  Clients are aggregated by prefix (so
+ * that, for example, all of the addresses in an IPv6 /64 are treated
+ * as a single client), and each aggregate has a token bucket for
+ * queries and a cap on the number of concurrent connections. */
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LimitAction {
+    /* Reject the query with a comment. */
+    Reject,
+    /* Hold the connection open for the tarpit delay, and then reject
+     * the query. */
+    Tarpit,
+    /* Reject the query, and reject all further connections from the
+     * client for the ban duration. */
+    Ban,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RateLimitConfig {
+    /* The number of queries per second permitted for each client
+     * (zero means no limit), and the number of queries that may be
+     * made in a burst. */
+    pub rate:              f64,
+    pub burst:             f64,
+    /* The prefix lengths used to aggregate client addresses. */
+    pub ipv4_prefix_len:   u8,
+    pub ipv6_prefix_len:   u8,
+    /* The maximum number of concurrent connections for each client
+     * (zero means no limit). */
+    pub max_connections:   usize,
+    pub action:            LimitAction,
+    pub tarpit_delay:      Duration,
+    pub ban_duration:      Duration,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> RateLimitConfig {
+        RateLimitConfig {
+            rate:            0.0,
+            burst:           10.0,
+            ipv4_prefix_len: 32,
+            ipv6_prefix_len: 64,
+            max_connections: 0,
+            action:          LimitAction::Reject,
+            tarpit_delay:    Duration::from_secs(10),
+            ban_duration:    Duration::from_secs(300),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Decision {
+    Allow,
+    Reject(String),
+    Tarpit(Duration, String),
+}
+
+struct Bucket {
+    tokens:  f64,
+    updated: Instant,
+}
+
+/* Sweep idle state after this many checks. */
+const SWEEP_INTERVAL: u64 = 1000;
+
+pub struct RateLimiter {
+    config:      RateLimitConfig,
+    buckets:     HashMap<IpAddr, Bucket>,
+    connections: HashMap<IpAddr, usize>,
+    bans:        HashMap<IpAddr, Instant>,
+    checks:      u64,
+}
+
+impl RateLimiter {
+    /* The prefix lengths are checked here, so that an invalid length
+     * causes an error on startup, rather than when the first client
+     * connects. */
+    pub fn new(config: RateLimitConfig) -> RateLimiter {
+        if config.ipv4_prefix_len > 32 {
+            panic!("invalid IPv4 client prefix length: {}",
+                   config.ipv4_prefix_len);
+        }
+        if config.ipv6_prefix_len > 128 {
+            panic!("invalid IPv6 client prefix length: {}",
+                   config.ipv6_prefix_len);
+        }
+        RateLimiter {
+            config,
+            buckets:     HashMap::new(),
+            connections: HashMap::new(),
+            bans:        HashMap::new(),
+            checks:      0,
+        }
+    }
+
+    /* Return the address of the aggregate that the client address
+     * belongs to. */
+    pub fn client_key(&self, address: IpAddr) -> IpAddr {
+        match address {
+            IpAddr::V4(ipv4_address) => {
+                let net = Ipv4Net::new(ipv4_address,
+                                       self.config.ipv4_prefix_len).unwrap();
+                IpAddr::V4(net.network())
+            },
+            IpAddr::V6(ipv6_address) => {
+                let net = Ipv6Net::new(ipv6_address,
+                                       self.config.ipv6_prefix_len).unwrap();
+                IpAddr::V6(net.network())
+            }
+        }
+    }
+
+    fn limited(&mut self, key: IpAddr, reason: &str,
+               now: Instant) -> Decision {
+        match self.config.action {
+            LimitAction::Reject => Decision::Reject(reason.to_owned()),
+            LimitAction::Tarpit => {
+                Decision::Tarpit(self.config.tarpit_delay, reason.to_owned())
+            },
+            LimitAction::Ban => {
+                self.bans.insert(key, now + self.config.ban_duration);
+                Decision::Reject(reason.to_owned())
+            }
+        }
+    }
+
+    fn sweep(&mut self, now: Instant) {
+        let rate = self.config.rate;
+        let burst = self.config.burst;
+        self.buckets.retain(|_, bucket| {
+            let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+            bucket.tokens + elapsed * rate < burst
+        });
+        self.bans.retain(|_, until| *until > now);
+    }
+
+    /* Check whether a new connection from the client is permitted.
+     * If the decision is not Reject, then the connection is counted
+     * towards the client's connection limit until disconnect is
+     * called. */
+    pub fn connect(&mut self, address: IpAddr, now: Instant) -> Decision {
+        let key = self.client_key(address);
+        self.checks += 1;
+        if self.checks >= SWEEP_INTERVAL {
+            self.checks = 0;
+            self.sweep(now);
+        }
+
+        if let Some(until) = self.bans.get(&key).cloned() {
+            if until > now {
+                return Decision::Reject("client is temporarily banned".to_owned());
+            }
+            self.bans.remove(&key);
+        }
+
+        let count = self.connections.get(&key).cloned().unwrap_or(0);
+        if self.config.max_connections > 0
+                && count >= self.config.max_connections {
+            let decision = self.limited(key, "too many connections", now);
+            if let Decision::Reject(_) = decision {
+                return decision;
+            }
+            *self.connections.entry(key).or_insert(0) += 1;
+            return decision;
+        }
+        *self.connections.entry(key).or_insert(0) += 1;
+        Decision::Allow
+    }
+
+    pub fn disconnect(&mut self, address: IpAddr) {
+        let key = self.client_key(address);
+        let remove = match self.connections.get_mut(&key) {
+            Some(count) => {
+                *count -= 1;
+                *count == 0
+            },
+            None => false
+        };
+        if remove {
+            self.connections.remove(&key);
+        }
+    }
+
+    /* Check whether a query from the client is permitted, consuming
+     * a token from the client's bucket if it is. */
+    pub fn query(&mut self, address: IpAddr, now: Instant) -> Decision {
+        if self.config.rate <= 0.0 {
+            return Decision::Allow;
+        }
+        let key = self.client_key(address);
+        if let Some(until) = self.bans.get(&key).cloned() {
+            if until > now {
+                return Decision::Reject("client is temporarily banned".to_owned());
+            }
+        }
+
+        let rate = self.config.rate;
+        let burst = self.config.burst;
+        let bucket = self.buckets.entry(key).or_insert(Bucket {
+            tokens:  burst,
+            updated: now,
+        });
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(burst);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Decision::Allow
+        } else {
+            self.limited(key, "query rate limit exceeded", now)
+        }
+    }
+}
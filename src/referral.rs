@@ -0,0 +1,41 @@
+use super::target::UpstreamTarget;
+
+use std::str::FromStr;
+
+/* Referral handling.  Some Whois servers respond to queries for
+ * resources that they are not authoritative for with a pointer to
+ * another server: IANA uses 'refer:' (and 'whois:' for some
+ * records), while ARIN uses 'ReferralServer:' with a URL-style
+ * value, which may refer to either a Whois or an RWhois server. */
+
+fn parse_referral_value(value: &str) -> Option<String> {
+    UpstreamTarget::from_str(value).ok().map(|target| target.to_string())
+}
+
+/* Return the server referred to by the given response, if any.  The
+ * server is returned in its canonical form (see target.rs), e.g.
+ * "whois.apnic.net", "whois.example.net:4343" or
+ * "rwhois://rwhois.example.net". */
+pub fn parse_referral(response: &str) -> Option<String> {
+    for line in response.lines() {
+        let line = line.trim();
+        if line.starts_with('%') || line.starts_with('#') {
+            continue;
+        }
+        let mut parts = line.splitn(2, ':');
+        let key = parts.next().unwrap().trim().to_lowercase();
+        let value = match parts.next() {
+            Some(value) => value,
+            None        => continue
+        };
+        match key.as_str() {
+            "refer" | "whois" | "referralserver" => {
+                if let Some(server) = parse_referral_value(value) {
+                    return Some(server);
+                }
+            },
+            _ => {}
+        }
+    }
+    None
+}
@@ -0,0 +1,346 @@
+extern crate csv;
+extern crate futures;
+extern crate futures_cpupool;
+extern crate tokio_core;
+
+use self::futures::{future, Async, Future, Poll};
+use self::futures_cpupool::CpuPool;
+use self::tokio_core::net::{TcpStream, TcpStreamNew};
+use self::tokio_core::reactor::{Handle, Timeout};
+
+use super::dns;
+
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::rc::Rc;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/* Resolution of upstream server names, and connections to upstream
+ * servers.  Names are resolved on a thread pool, so that slow lookups
+ * do not block the reactor thread.  Fully-qualified names are resolved
+ * by querying the configured nameservers directly (see dns.rs), and
+ * the results are cached for the lowest TTL of the records, up to the
+ * configured cache TTL.  Other names, and names that cannot be
+ * resolved that way, are resolved by the system resolver, which does
+ * not expose record TTLs, and the results are cached for the
+ * configured cache TTL.  Connections are made using the 'Happy Eyeballs' approach (RFC
+ * 8305): the addresses are tried in turn, alternating between address
+ * families, with a new attempt started whenever the previous attempt
+ * fails or has not succeeded within the attempt delay. */
+
+const DNS_THREADS: usize = 4;
+
+/* The time to wait for a response from a nameserver. */
+const DNS_TIMEOUT_SECONDS: u64 = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AddressFamily {
+    /* Use the addresses in the order returned by the system
+     * resolver. */
+    Any,
+    PreferIpv4,
+    PreferIpv6,
+    /* Use only addresses of the given family. */
+    Ipv4,
+    Ipv6,
+}
+
+/* A static mapping from hostname to addresses, which takes precedence
+ * over the system resolver. */
+#[derive(Clone, Debug, Default)]
+pub struct HostsTable {
+    hosts: HashMap<String, Vec<IpAddr>>,
+}
+
+impl HostsTable {
+    /* Load the table from a CSV file, where each line contains a
+     * hostname followed by one or more addresses. */
+    pub fn from_file(path: &str) -> HostsTable {
+        let file = File::open(path).unwrap();
+        let mut csv_reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(file);
+        let mut hosts: HashMap<String, Vec<IpAddr>> = HashMap::new();
+        csv_reader.records()
+            .map(|i| i.unwrap())
+            .for_each(|i| {
+                let host = i.get(0).unwrap().trim().to_lowercase();
+                let addresses =
+                    i.iter().skip(1)
+                        .map(|address| IpAddr::from_str(address.trim()).unwrap());
+                hosts.entry(host).or_default().extend(addresses);
+            });
+        HostsTable { hosts }
+    }
+
+    pub fn get(&self, host: &str) -> Option<&Vec<IpAddr>> {
+        self.hosts.get(&host.to_lowercase())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ResolverConfig {
+    pub family:        AddressFamily,
+    /* The maximum time for which resolved addresses are cached.
+     * Addresses are cached for the TTL of their records, if that is
+     * lower, and for this time if the TTL is not known. */
+    pub cache_ttl:     Duration,
+    /* The time to wait for a connection attempt to succeed before
+     * starting an attempt to the next address. */
+    pub attempt_delay: Duration,
+    pub hosts:         HostsTable,
+    /* The nameservers to query for the address records of upstream
+     * server names. */
+    pub nameservers:   Vec<SocketAddr>,
+}
+
+impl Default for ResolverConfig {
+    fn default() -> ResolverConfig {
+        ResolverConfig {
+            family:        AddressFamily::Any,
+            cache_ttl:     Duration::from_secs(60),
+            attempt_delay: Duration::from_millis(250),
+            hosts:         HostsTable::default(),
+            nameservers:   dns::nameservers_from_file(dns::RESOLV_CONF),
+        }
+    }
+}
+
+/* Order the addresses for connection attempts, according to the
+ * address family setting.  Addresses from each family are
+ * interleaved, starting with the preferred family (or with the family
+ * of the first address, if there is no preference), and the relative
+ * order of addresses within a family is preserved. */
+pub fn order_addresses(addresses: &[IpAddr],
+                       family: AddressFamily) -> Vec<IpAddr> {
+    let (ipv4, ipv6): (Vec<IpAddr>, Vec<IpAddr>) =
+        addresses.iter().partition(|address| address.is_ipv4());
+    let ipv4_first = match family {
+        AddressFamily::Ipv4       => return ipv4,
+        AddressFamily::Ipv6       => return ipv6,
+        AddressFamily::PreferIpv4 => true,
+        AddressFamily::PreferIpv6 => false,
+        AddressFamily::Any        => {
+            addresses.first().map(|a| a.is_ipv4()).unwrap_or(true)
+        }
+    };
+    let (first, second) =
+        if ipv4_first { (ipv4, ipv6) } else { (ipv6, ipv4) };
+    let mut ordered = Vec::with_capacity(addresses.len());
+    let mut first = first.into_iter();
+    let mut second = second.into_iter();
+    loop {
+        match (first.next(), second.next()) {
+            (None, None) => break,
+            (a, b) => {
+                ordered.extend(a);
+                ordered.extend(b);
+            }
+        }
+    }
+    ordered
+}
+
+type AddressFuture = Box<dyn Future<Item = Vec<IpAddr>, Error = io::Error>>;
+
+/* Resolved addresses, keyed by hostname, with their expiry times. */
+type AddressCache = Rc<RefCell<HashMap<String, (Vec<IpAddr>, Instant)>>>;
+
+#[derive(Clone)]
+pub struct Resolver {
+    config: ResolverConfig,
+    pool:   CpuPool,
+    cache:  AddressCache,
+    handle: Handle,
+}
+
+impl Resolver {
+    pub fn new(config: ResolverConfig, handle: &Handle) -> Resolver {
+        Resolver {
+            config,
+            pool:   CpuPool::new(DNS_THREADS),
+            cache:  Rc::new(RefCell::new(HashMap::new())),
+            handle: handle.clone(),
+        }
+    }
+
+    /* Return the addresses for the host, in the order in which they
+     * should be tried.  Resolved addresses are cached as described
+     * above. */
+    pub fn resolve(&self, host: &str) -> AddressFuture {
+        let family = self.config.family;
+        let order = move |addresses: Vec<IpAddr>| {
+            let ordered = order_addresses(&addresses, family);
+            if ordered.is_empty() {
+                Err(io::Error::new(io::ErrorKind::NotFound,
+                                   "no addresses found"))
+            } else {
+                Ok(ordered)
+            }
+        };
+
+        if let Ok(address) = IpAddr::from_str(host) {
+            return Box::new(future::result(order(vec![address])));
+        }
+        if let Some(addresses) = self.config.hosts.get(host) {
+            return Box::new(future::result(order(addresses.clone())));
+        }
+        let key = host.to_lowercase();
+        if let Some(&(ref addresses, expires)) = self.cache.borrow().get(&key) {
+            if expires > Instant::now() {
+                return Box::new(future::result(order(addresses.clone())));
+            }
+        }
+
+        let cache = self.cache.clone();
+        let cache_ttl = self.config.cache_ttl;
+        let nameservers = self.config.nameservers.clone();
+        let lookup_host = key.clone();
+        let lookup = self.pool.spawn_fn(move || {
+            lookup_addresses(&lookup_host, &nameservers, cache_ttl)
+        });
+        Box::new(lookup.and_then(move |(addresses, ttl)| {
+            cache.borrow_mut().insert(key, (addresses.clone(),
+                                            Instant::now() + ttl));
+            order(addresses)
+        }))
+    }
+
+    /* Connect to the given port on the host. */
+    pub fn connect(&self, host: &str, port: u16)
+            -> Box<dyn Future<Item = TcpStream, Error = io::Error>> {
+        let attempt_delay = self.config.attempt_delay;
+        let handle = self.handle.clone();
+        let server = host.to_owned();
+        Box::new(
+            self.resolve(host)
+                .map_err(move |e| {
+                    io::Error::new(e.kind(),
+                                   format!("unable to resolve {}: {}",
+                                           server, e))
+                })
+                .and_then(move |addresses| {
+                    let socket_addrs =
+                        addresses.into_iter()
+                            .map(|address| SocketAddr::new(address, port))
+                            .collect();
+                    connect_addresses(socket_addrs, attempt_delay, &handle)
+                })
+        )
+    }
+}
+
+/* Return the addresses for the host, and the time for which they may
+ * be cached.  This blocks, so it is run on the thread pool. */
+fn lookup_addresses(host: &str, nameservers: &[SocketAddr],
+                    cache_ttl: Duration) -> io::Result<(Vec<IpAddr>, Duration)> {
+    if host.contains('.') {
+        let timeout = Duration::from_secs(DNS_TIMEOUT_SECONDS);
+        match dns::lookup(host, nameservers, timeout) {
+            Ok(ref answer) if !answer.addresses.is_empty() => {
+                let ttl =
+                    answer.ttl.map(|ttl| Duration::from_secs(ttl as u64))
+                        .unwrap_or(cache_ttl);
+                return Ok((answer.addresses.clone(), ttl.min(cache_ttl)));
+            },
+            Ok(_) => {},
+            Err(e) => {
+                debug!("unable to resolve {} using nameservers: {}", host, e);
+            }
+        }
+    }
+    let mut addresses: Vec<IpAddr> = Vec::new();
+    for socket_addr in (host, 0).to_socket_addrs()? {
+        if !addresses.contains(&socket_addr.ip()) {
+            addresses.push(socket_addr.ip());
+        }
+    }
+    Ok((addresses, cache_ttl))
+}
+
+pub struct HappyEyeballs {
+    addresses:     VecDeque<SocketAddr>,
+    attempts:      Vec<TcpStreamNew>,
+    delay:         Option<Timeout>,
+    attempt_delay: Duration,
+    start_now:     bool,
+    last_error:    Option<io::Error>,
+    handle:        Handle,
+}
+
+/* Connect to one of the addresses, trying each in order as described
+ * above.  The result is the first connection to succeed, or the last
+ * error if no connection succeeds. */
+pub fn connect_addresses(addresses: Vec<SocketAddr>, attempt_delay: Duration,
+                         handle: &Handle) -> HappyEyeballs {
+    HappyEyeballs {
+        addresses:  addresses.into_iter().collect(),
+        attempts:   Vec::new(),
+        delay:      None,
+        attempt_delay,
+        start_now:  false,
+        last_error: None,
+        handle:     handle.clone(),
+    }
+}
+
+impl Future for HappyEyeballs {
+    type Item = TcpStream;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<TcpStream, io::Error> {
+        loop {
+            let mut start = self.attempts.is_empty() || self.start_now;
+            let delay_elapsed = match self.delay {
+                Some(ref mut delay) => delay.poll()?.is_ready(),
+                None                => false
+            };
+            if delay_elapsed {
+                self.delay = None;
+                start = true;
+            }
+            if start {
+                self.start_now = false;
+                if let Some(address) = self.addresses.pop_front() {
+                    self.attempts.push(TcpStream::connect(&address,
+                                                          &self.handle));
+                    self.delay =
+                        if self.addresses.is_empty() {
+                            None
+                        } else {
+                            Some(Timeout::new(self.attempt_delay,
+                                              &self.handle)?)
+                        };
+                    continue;
+                }
+            }
+            if self.attempts.is_empty() {
+                return Err(self.last_error.take().unwrap_or_else(|| {
+                    io::Error::new(io::ErrorKind::NotFound,
+                                   "no addresses found")
+                }));
+            }
+
+            let mut index = 0;
+            while index < self.attempts.len() {
+                match self.attempts[index].poll() {
+                    Ok(Async::Ready(stream)) => return Ok(Async::Ready(stream)),
+                    Ok(Async::NotReady) => { index += 1; },
+                    Err(e) => {
+                        drop(self.attempts.remove(index));
+                        self.last_error = Some(e);
+                        self.start_now = true;
+                    }
+                }
+            }
+            if !self.start_now {
+                return Ok(Async::NotReady);
+            }
+        }
+    }
+}
@@ -0,0 +1,185 @@
+extern crate futures;
+extern crate tokio_io;
+
+use self::futures::Future;
+use self::futures::future::{self, loop_fn, Either, Loop};
+use self::tokio_io::{AsyncRead, AsyncWrite};
+use self::tokio_io::io::{read, write_all};
+
+use std::io;
+
+/* RWhois (RFC 2167) client support.  On connection, an RWhois server
+ * sends a banner line ('%rwhois V-1.5 ...').  The client then sends
+ * its query, and the server responds with a series of lines, ending
+ * with either '%ok' or '%error <code> <message>'.  The connection
+ * stays open for further queries until the client sends '-quit'.
+ *
+ * Each object in the response is a series of lines of the form
+ * 'class:attribute:value'.  These are translated into plain Whois
+ * text ('attribute: value'), directives such as '%error' become
+ * comments, and '%referral' directives become 'ReferralServer:'
+ * lines, so that they can be followed in the same way as ARIN
+ * referrals. */
+
+/* The width of the attribute name column in translated responses. */
+const ATTRIBUTE_WIDTH: usize = 15;
+
+type ReadFuture<S> = Box<dyn Future<Item = (S, Vec<u8>, Option<usize>),
+                                    Error = io::Error>>;
+
+fn protocol_error(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/* Return the length of the data up to and including the first
+ * complete line for which the predicate is true. */
+fn find_line(buffer: &[u8], predicate: fn(&str) -> bool) -> Option<usize> {
+    let mut start = 0;
+    while let Some(index) = buffer[start..].iter().position(|&b| b == b'\n') {
+        let end = start + index + 1;
+        let line = String::from_utf8_lossy(&buffer[start..end]);
+        if predicate(line.trim()) {
+            return Some(end);
+        }
+        start = end;
+    }
+    None
+}
+
+/* Read from the stream into the buffer until it contains a line for
+ * which the predicate is true.  The result includes the length of the
+ * data up to and including that line, or None if the stream was
+ * closed first. */
+fn read_until<S>(stream: S, buffer: Vec<u8>, predicate: fn(&str) -> bool)
+        -> ReadFuture<S>
+        where S: AsyncRead + 'static {
+    Box::new(loop_fn((stream, buffer), move |(stream, mut buffer)| {
+        if let Some(end) = find_line(&buffer, predicate) {
+            return Either::A(future::ok(Loop::Break((stream, buffer,
+                                                     Some(end)))));
+        }
+        Either::B(read(stream, vec![0; 4096]).map(move |(stream, data, n)| {
+            if n == 0 {
+                return Loop::Break((stream, buffer, None));
+            }
+            buffer.extend_from_slice(&data[..n]);
+            Loop::Continue((stream, buffer))
+        }))
+    }))
+}
+
+/* Return whether the line is the last line of a response. */
+pub fn is_final_line(line: &str) -> bool {
+    let lower = line.to_lowercase();
+    lower == "%ok" || lower.starts_with("%error")
+}
+
+/* Read the server's banner.  The result is the stream, along with any
+ * data received after the banner. */
+pub fn read_banner<S>(stream: S)
+        -> Box<dyn Future<Item = (S, Vec<u8>), Error = io::Error>>
+        where S: AsyncRead + 'static {
+    Box::new(read_until(stream, Vec::new(), |_| true).and_then(
+        |(stream, buffer, end)| {
+            let end = match end {
+                Some(end) => end,
+                None => {
+                    return Err(protocol_error(
+                        "connection closed before RWhois banner".to_owned()
+                    ));
+                }
+            };
+            let banner = String::from_utf8_lossy(&buffer[..end])
+                .trim().to_owned();
+            let lower = banner.to_lowercase();
+            if lower.starts_with("%error") {
+                return Err(protocol_error(
+                    format!("RWhois server error: {}", &banner[6..].trim())
+                ));
+            }
+            if !lower.starts_with("%rwhois") {
+                return Err(protocol_error(
+                    format!("unexpected RWhois banner: {}", banner)
+                ));
+            }
+            Ok((stream, buffer[end..].to_vec()))
+        }
+    ))
+}
+
+/* Send the query (which must include the line terminator) and return
+ * the response, translated into plain Whois text.  'buffer' is the
+ * data received after the banner. */
+pub fn query<S>(stream: S, buffer: Vec<u8>, query_data: String)
+        -> Box<dyn Future<Item = Vec<u8>, Error = io::Error>>
+        where S: AsyncRead + AsyncWrite + 'static {
+    Box::new(
+        write_all(stream, query_data)
+            .and_then(move |(stream, _)| {
+                read_until(stream, buffer, is_final_line)
+            })
+            .and_then(|(stream, mut buffer, end)| {
+                /* If the server closed the connection before the end
+                 * of the response, then the partial response is
+                 * returned. */
+                if let Some(end) = end {
+                    buffer.truncate(end);
+                }
+                let response =
+                    translate_response(&String::from_utf8_lossy(&buffer));
+                write_all(stream, b"-quit\r\n")
+                    .then(move |_| Ok(response.into_bytes()))
+            })
+    )
+}
+
+/* Return the server from a '%referral' directive, without any
+ * authority area that follows the host and port. */
+fn referral_server(url: &str) -> &str {
+    let start = url.find("://").map(|i| i + 3).unwrap_or(0);
+    match url[start..].find('/') {
+        Some(index) => &url[..start + index],
+        None        => url
+    }
+}
+
+fn translate_line(line: &str) -> Option<String> {
+    let trimmed = line.trim();
+    if let Some(directive_line) = trimmed.strip_prefix('%') {
+        let mut parts = directive_line.splitn(2, char::is_whitespace);
+        let directive = parts.next().unwrap().to_lowercase();
+        let value = parts.next().unwrap_or("").trim();
+        return match directive.as_str() {
+            "ok"       => None,
+            "error"    => Some(format!("% RWhois error {}", value)),
+            "referral" => Some(format!("ReferralServer: {}",
+                                       referral_server(value))),
+            _          => Some(format!("% {}", directive_line.trim())),
+        };
+    }
+    let mut parts = trimmed.splitn(3, ':');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(class), Some(attribute), Some(value))
+                if !class.is_empty() && !attribute.is_empty()
+                    && !class.contains(char::is_whitespace) => {
+            /* Strip any type suffix from the attribute name (e.g.
+             * 'Tech-Contact;I'). */
+            let attribute = attribute.split(';').next().unwrap();
+            Some(format!("{:width$} {}", format!("{}:", attribute),
+                         value.trim(), width = ATTRIBUTE_WIDTH))
+        },
+        _ => Some(trimmed.to_owned())
+    }
+}
+
+/* Translate an RWhois response into plain Whois text. */
+pub fn translate_response(response: &str) -> String {
+    let mut output = String::new();
+    for line in response.lines() {
+        if let Some(line) = translate_line(line) {
+            output.push_str(&line);
+            output.push('\n');
+        }
+    }
+    output
+}
@@ -4,21 +4,42 @@ extern crate notify;
 extern crate tokio_core;
 extern crate tokio_io;
 
-use super::context::Context;
+use super::cache::ResponseCache;
+use super::coalesce::Coalescer;
+use super::config::{Config, ReferralOutput};
+use super::context::{Context, parse_domain, parse_resource, parse_resources};
+use super::input::{FirstLine, Input, first_line, query_lines};
+use super::limiter::Limiter;
+use super::listener::{self, Connection, ListenAddress, Listener};
 use super::lookup::ResourceLookup;
+use super::pool::{DEFAULT_POOL_SIZE, SessionPool, read_response};
+use super::proxy::{self, read_proxy_header};
+use super::query::Query;
+use super::ratelimit::{Decision, RateLimiter};
+use super::referral::parse_referral;
+use super::resolver::Resolver;
+use super::rwhois;
+use super::session::{Session, session_block, session_inputs,
+                     with_idle_timeout};
+use super::target::{UpstreamProtocol, UpstreamTarget};
+use super::tls::{TlsAcceptor, TlsConfig, TlsConnectors};
+use super::upstream::Timeouts;
 
 use self::futures::{Future, Stream, Poll};
+use self::futures::future::{self, loop_fn, Loop};
+use self::futures::stream;
 use self::ipnet::Ipv4Net;
 use self::notify::{PollWatcher, Watcher, RecursiveMode,
                    DebouncedEvent};
-use self::tokio_core::net::{TcpListener, TcpStream};
-use self::tokio_core::reactor::Core;
+use self::tokio_core::net::TcpStream;
+use self::tokio_core::reactor::{Core, Handle, Interval, Timeout};
 use self::tokio_io::{AsyncRead, AsyncWrite};
-use self::tokio_io::io::{copy, shutdown, lines, write_all};
+use self::tokio_io::io::{shutdown, read, read_to_end, write_all};
 
-use std::io::{self, Read, Write, BufReader};
-use std::net::{Shutdown, ToSocketAddrs};
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, Shutdown, SocketAddr};
 use std::ops::Sub;
+use std::path::Path;
 use std::str::FromStr;
 use std::sync::Arc;
 use std::sync::Mutex;
@@ -28,12 +49,36 @@ use std::time::{Duration, Instant};
 
 const RELOAD_DELAY: u64 = 15;
 const POLL_PERIOD:  u64 = 5;
+const STATS_PERIOD: u64 = 60;
+
+type QueryFuture = Box<dyn Future<Item = Vec<u8>, Error = io::Error>>;
+
+/* A query that follows referrals: the item is the response, and
+ * whether it is complete (false if a server that was referred to
+ * could not be queried). */
+type ReferralFuture =
+    Box<dyn Future<Item = (Vec<u8>, bool), Error = io::Error>>;
+
+/* State shared by the connection handlers. */
+#[derive(Clone)]
+struct State {
+    config:       Arc<Config>,
+    cache:        Arc<Mutex<ResponseCache>>,
+    in_flight:    Coalescer<(Vec<u8>, bool)>,
+    limiter:      Limiter,
+    rate_limiter: Arc<Mutex<RateLimiter>>,
+    resolver:     Resolver,
+    tls_connectors: TlsConnectors,
+    sessions:     SessionPool<Box<dyn Connection>>,
+    handle:       Handle,
+}
 
 lazy_static! {
     static ref CONTEXT: Arc<Mutex<Context>> = {
         Arc::new(Mutex::new(Context::from_files("data/ipv4",
                                                 "data/ipv6",
-                                                "data/asn")))
+                                                "data/asn",
+                                                "data/domain")))
     };
 }
 
@@ -46,6 +91,10 @@ fn watch() -> notify::Result<()> {
     watcher.watch("data/ipv4", RecursiveMode::NonRecursive).unwrap();
     watcher.watch("data/ipv6", RecursiveMode::NonRecursive).unwrap();
     watcher.watch("data/asn",  RecursiveMode::NonRecursive).unwrap();
+    /* The domain file is optional (see context.rs). */
+    if Path::new("data/domain").exists() {
+        watcher.watch("data/domain", RecursiveMode::NonRecursive).unwrap();
+    }
 
     let mut last_event_time = Instant::now();
 
@@ -62,10 +111,12 @@ fn watch() -> notify::Result<()> {
                               RELOAD_DELAY);
                         thread::sleep(Duration::from_secs(RELOAD_DELAY));
                         info!("Reloading data");
-                        let new_context = Context::from_files("data/ipv4",
-                                                              "data/ipv6",
-                                                              "data/asn");
+                        let mut new_context = Context::from_files("data/ipv4",
+                                                                  "data/ipv6",
+                                                                  "data/asn",
+                                                                  "data/domain");
                         let mut context = CONTEXT.lock().unwrap();
+                        new_context.embedded_ipv4 = context.embedded_ipv4;
                         *context = new_context;
                         info!("Finished reloading data");
                     });
@@ -77,32 +128,891 @@ fn watch() -> notify::Result<()> {
     }
 }
 
+/* Reload the TLS certificate and key when the files change. */
+fn watch_tls(config: TlsConfig, acceptor: TlsAcceptor) -> notify::Result<()> {
+    let (tx, rx) = channel();
+
+    let mut watcher =
+        PollWatcher::new(tx, Duration::from_secs(POLL_PERIOD))?;
+
+    watcher.watch(&config.certificate, RecursiveMode::NonRecursive)?;
+    watcher.watch(&config.key, RecursiveMode::NonRecursive)?;
+    if let Some(ref client_ca) = config.client_ca {
+        watcher.watch(client_ca, RecursiveMode::NonRecursive)?;
+    }
+
+    let mut last_event_time = Instant::now();
+
+    loop {
+        match rx.recv() {
+            Ok(DebouncedEvent::Write(_)) | Ok(DebouncedEvent::Create(_)) => {
+                let current_event_time = Instant::now();
+                let difference = current_event_time.sub(last_event_time);
+                let test_duration = Duration::from_secs(RELOAD_DELAY);
+                if difference.ge(&test_duration) {
+                    last_event_time = Instant::now();
+                    let config = config.clone();
+                    let acceptor = acceptor.clone();
+                    thread::spawn(move || {
+                        info!("Detected change to TLS certificate, waiting {}s before reloading",
+                              RELOAD_DELAY);
+                        thread::sleep(Duration::from_secs(RELOAD_DELAY));
+                        match acceptor.reload(&config) {
+                            Ok(_)  => info!("Finished reloading TLS certificate"),
+                            Err(e) => error!("Unable to reload TLS certificate: {}", e)
+                        }
+                    });
+                }
+            },
+            Err(e) => error!("Poll error: {:?}", e),
+            _ => {}
+        }
+    }
+}
+
 fn duration_to_ms(duration: Duration) -> u64 {
     let ms_secs: u64 = duration.as_secs() * 1000;
     let ns_secs: u64 = (duration.subsec_nanos() / 1000000) as u64;
     ms_secs + ns_secs
 }
 
-pub fn run(default_server_option: Option<String>,
-           port_option: Option<String>) {
-    let default_server =
-        match default_server_option {
-            Some(hostname) => hostname,
-            None           => "whois.iana.org".to_owned()
+/* Fail with a 'timed out' error if the future does not complete
+ * within the given duration. */
+fn with_timeout<F>(future: F, duration: Duration, message: String,
+                   handle: &Handle)
+        -> Box<dyn Future<Item = F::Item, Error = io::Error>>
+        where F: Future<Error = io::Error> + 'static,
+              F::Item: 'static {
+    let timeout = match Timeout::new(duration, handle) {
+        Ok(timeout) => timeout,
+        Err(e)      => return Box::new(future::err(e))
+    };
+    let timeout_error = timeout.and_then(move |_| {
+        Err(io::Error::new(io::ErrorKind::TimedOut, message))
+    });
+    Box::new(future.select(timeout_error)
+                   .map(|(item, _)| item)
+                   .map_err(|(e, _)| e))
+}
+
+/* Connect to the given server, using TLS if required by the server's
+ * target or upstream options. */
+fn connect_server(server: &str, state: &State)
+        -> Box<dyn Future<Item = Box<dyn Connection>, Error = io::Error>> {
+    let target = match UpstreamTarget::from_str(server) {
+        Ok(target) => target,
+        Err(e) => {
+            return Box::new(future::err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid server {}: {}", server, e)
+            )));
+        }
+    };
+    let options = state.config.upstream.get(server);
+    let connect = state.resolver.connect(&target.host, target.port);
+    if !target.tls && !options.tls {
+        return Box::new(connect.map(|stream| {
+            Box::new(stream) as Box<dyn Connection>
+        }));
+    }
+    let host = target.host;
+    let ca_file = options.tls_ca_file.clone();
+    let connectors = state.tls_connectors.clone();
+    Box::new(connect.and_then(move |stream| {
+        connectors.connect(&host, ca_file.as_deref(),
+                           stream)
+            .map(|stream| Box::new(stream) as Box<dyn Connection>)
+    }))
+}
+
+fn query_server(server: &str, query: &str, timeouts: Timeouts,
+                state: &State)
+        -> QueryFuture {
+    let handle = &state.handle;
+    let mut query_data = query.to_string();
+    query_data.push_str("\r\n");
+
+    let is_rwhois =
+        UpstreamTarget::from_str(server)
+            .map(|target| target.protocol == UpstreamProtocol::Rwhois)
+            .unwrap_or(false);
+    if !is_rwhois && state.config.upstream.get(server).keep_alive {
+        return query_pooled(server, query_data, timeouts, state);
+    }
+
+    let connect = with_timeout(connect_server(server, state),
+                               timeouts.connect,
+                               format!("connection to {} timed out", server),
+                               handle);
+    let server_ = server.to_string();
+    let handle_ = handle.clone();
+    if is_rwhois {
+        let exchange = connect
+            .and_then(move |server| {
+                with_timeout(rwhois::read_banner(server),
+                             timeouts.first_byte,
+                             format!("response from {} timed out", server_),
+                             &handle_)
+            })
+            .and_then(move |(server, buffer)| {
+                rwhois::query(server, buffer, query_data)
+            });
+        return with_timeout(exchange, timeouts.total,
+                            format!("query to {} timed out", server),
+                            handle);
+    }
+    let exchange = connect
+        .and_then(move |server| write_all(server, query_data))
+        .and_then(move |(server, _)| {
+            with_timeout(read(server, vec![0; 4096]),
+                         timeouts.first_byte,
+                         format!("response from {} timed out", server_),
+                         &handle_)
+        })
+        .and_then(|(server, mut response, n)| {
+            response.truncate(n);
+            read_to_end(server, response)
+        })
+        .map(|(_, response)| response);
+    with_timeout(exchange, timeouts.total,
+                 format!("query to {} timed out", server),
+                 handle)
+}
+
+/* Query the server over a persistent connection (see pool.rs).  If no
+ * connection is given, then a new connection is made, and the '-k'
+ * flag is sent with the query to make the connection persistent.
+ * Once the response has been received, the connection is returned to
+ * the pool. */
+fn query_session(server: &str, query_data: String, timeouts: Timeouts,
+                 connection: Option<Box<dyn Connection>>, state: &State)
+        -> QueryFuture {
+    let handle = &state.handle;
+    let (connect, query_data): (Box<dyn Future<Item = Box<dyn Connection>,
+                                                Error = io::Error>>,
+                                String) =
+        match connection {
+            Some(connection) => (Box::new(future::ok(connection)), query_data),
+            None => {
+                let connect =
+                    with_timeout(connect_server(server, state),
+                                 timeouts.connect,
+                                 format!("connection to {} timed out", server),
+                                 handle);
+                (connect, format!("-k {}", query_data))
+            }
         };
-    let port =
-        match port_option {
-            Some(port) => port,
-            None       => "4343".to_owned()
+    let pool_size = state.config.upstream.get(server).pool_size
+                         .unwrap_or(DEFAULT_POOL_SIZE);
+    let sessions = state.sessions.clone();
+    let pool_server = server.to_string();
+    let server_ = server.to_string();
+    let handle_ = handle.clone();
+    let exchange = connect
+        .and_then(move |connection| write_all(connection, query_data))
+        .and_then(move |(connection, _)| {
+            with_timeout(read(connection, vec![0; 4096]),
+                         timeouts.first_byte,
+                         format!("response from {} timed out", server_),
+                         &handle_)
+        })
+        .and_then(|(connection, mut response, n)| {
+            response.truncate(n);
+            read_response(connection, response)
+        })
+        .map(move |(connection, response)| {
+            if let Some(connection) = connection {
+                sessions.put(&pool_server, connection, pool_size,
+                             Instant::now());
+            }
+            response
+        });
+    with_timeout(exchange, timeouts.total,
+                 format!("query to {} timed out", server),
+                 handle)
+}
+
+/* Query the server using a pooled persistent connection, if there is
+ * one.  If the pooled connection fails (e.g. because the server has
+ * closed it), then the query is retried on a new connection. */
+fn query_pooled(server: &str, query_data: String, timeouts: Timeouts,
+                state: &State)
+        -> QueryFuture {
+    let connection = match state.sessions.take(server, Instant::now()) {
+        Some(connection) => connection,
+        None => return query_session(server, query_data, timeouts, None, state)
+    };
+    let server_ = server.to_string();
+    let query_data_ = query_data.clone();
+    let state_ = state.clone();
+    Box::new(query_session(server, query_data, timeouts, Some(connection),
+                           state)
+        .or_else(move |e| -> QueryFuture {
+            if e.kind() == io::ErrorKind::TimedOut {
+                return Box::new(future::err(e));
+            }
+            debug!("Persistent connection to {} failed ({}), reconnecting",
+                   server_, e);
+            query_session(&server_, query_data_, timeouts, None, &state_)
+        }))
+}
+
+/* Query the given server, once a connection to that server is
+ * permitted by the limiter. */
+fn query_server_limited(server: &str, query: &Query, state: &State)
+        -> QueryFuture {
+    let config = &state.config;
+    let query_data = config.upstream.rewrite(server, query);
+    let timeouts = config.upstream.timeouts(server, config.timeouts);
+    let options = config.upstream.get(server);
+    let max_connections =
+        options.max_connections.unwrap_or(config.max_connections);
+    let queue_timeout =
+        options.queue_timeout.unwrap_or(config.queue_timeout);
+
+    let acquire = state.limiter.acquire(server, max_connections)
+        .map_err(|_| io::Error::other("connection queue failed"));
+    let permit = with_timeout(acquire, queue_timeout,
+                              format!("timed out waiting for a connection to {}",
+                                      server),
+                              &state.handle);
+    let server_ = server.to_string();
+    let state_ = state.clone();
+    Box::new(permit.and_then(move |permit| {
+        query_server(&server_, &query_data, timeouts, &state_)
+            .then(move |result| {
+                drop(permit);
+                result
+            })
+    }))
+}
+
+/* Query the given server, following any referrals in the responses
+ * until either a response without a referral is received, the
+ * referral limit is reached, or a server is referred to for a second
+ * time.  If a server that was referred to cannot be queried, then
+ * the output accumulated up to that point is returned, marked as
+ * incomplete. */
+fn query_with_referrals(server: String, query: Query, state: State)
+        -> ReferralFuture {
+    let initial_state = (server.to_lowercase(), Vec::new(), Vec::new());
+    Box::new(loop_fn(initial_state, move |(server, mut visited, mut output):
+                                         (String, Vec<String>, Vec<u8>)| {
+        let query_ = query.original.clone();
+        let config_ = state.config.clone();
+        query_server_limited(&server, &query, &state).then(move |result| {
+            let response = match result {
+                Ok(response) => response,
+                Err(e) => {
+                    if visited.is_empty() {
+                        return Err(e);
+                    }
+                    error!("'{}' referral to {} failed: {}",
+                           &query_, server, e);
+                    return Ok(Loop::Break((output, false)));
+                }
+            };
+            match config_.referral_output {
+                ReferralOutput::Final => {
+                    output = response.clone();
+                },
+                ReferralOutput::Chain => {
+                    if !output.is_empty() {
+                        output.extend_from_slice(b"\n");
+                    }
+                    output.extend_from_slice(
+                        format!("% Response from {}:\n\n", server).as_bytes()
+                    );
+                    output.extend_from_slice(&response);
+                }
+            }
+            visited.push(server);
+
+            let referral =
+                parse_referral(&String::from_utf8_lossy(&response));
+            match referral {
+                Some(next) => {
+                    if visited.contains(&next) {
+                        info!("'{}' referral to {} ignored (loop)",
+                              &query_, next);
+                        Ok(Loop::Break((output, true)))
+                    } else if visited.len() > config_.max_referrals as usize {
+                        info!("'{}' referral to {} ignored (hop limit reached)",
+                              &query_, next);
+                        Ok(Loop::Break((output, true)))
+                    } else {
+                        info!("'{}' following referral to {}",
+                              &query_, next);
+                        Ok(Loop::Continue((next, visited, output)))
+                    }
+                },
+                None => Ok(Loop::Break((output, true)))
+            }
+        })
+    }))
+}
+
+/* Query the given server (following referrals).  If an identical
+ * query to the same server is already in progress, then its result
+ * is shared, rather than a new query being made. */
+fn query_coalesced(server: String, query: Query, state: State)
+        -> ReferralFuture {
+    let in_flight = state.in_flight.clone();
+    let original = query.original.clone();
+    let server_ = server.clone();
+    in_flight.query(&original, &server_, move || {
+        query_with_referrals(server, query, state)
+    })
+}
+
+/* Query the given server (following referrals), using the cached
+ * response for the query if there is one.  If the query fails and
+ * there is an expired response for the query in the cache, then that
+ * response is returned instead, if permitted. */
+fn query_with_cache(server: String, query: Query, state: State)
+        -> QueryFuture {
+    let cache = state.cache.clone();
+    if !cache.lock().unwrap().is_enabled() {
+        return Box::new(query_coalesced(server, query, state)
+                            .map(|(response, _)| response));
+    }
+    let cached =
+        cache.lock().unwrap().get(&query.original, &server, Instant::now());
+    if let Some(response) = cached {
+        info!("'{}' for {} found in cache", &query.original, server);
+        return Box::new(future::ok(response));
+    }
+
+    let query_ = query.original.clone();
+    Box::new(query_coalesced(server.clone(), query, state)
+        .then(move |result| {
+            let mut cache = cache.lock().unwrap();
+            match result {
+                Ok((response, true)) => {
+                    cache.insert(&query_, &server, response.clone(),
+                                 Instant::now());
+                    Ok(response)
+                },
+                Ok((response, false)) => {
+                    cache.insert_partial(&query_, &server, response.clone(),
+                                         Instant::now());
+                    Ok(response)
+                },
+                Err(e) => {
+                    match cache.get_stale(&query_, &server) {
+                        Some(response) => {
+                            warn!("'{}' for {} failed ({}), using stale cache entry",
+                                  &query_, server, e);
+                            Ok(response)
+                        },
+                        None => Err(e)
+                    }
+                }
+            }
+        }))
+}
+
+/* Log the connection limiter statistics for each upstream server,
+ * periodically. */
+fn log_limiter_stats(limiter: Limiter, handle: &Handle)
+        -> Box<dyn Future<Item = (), Error = io::Error>> {
+    let interval = match Interval::new(Duration::from_secs(STATS_PERIOD),
+                                       handle) {
+        Ok(interval) => interval,
+        Err(e)       => return Box::new(future::err(e))
+    };
+    Box::new(interval.for_each(move |_| {
+        for (server, stats) in limiter.all_stats() {
+            let average_wait =
+                match stats.waited {
+                    0      => 0,
+                    waited => stats.total_wait.as_millis() / waited as u128
+                };
+            debug!("Connections to {}: {} active, {} queued (max {}), \
+                    {} waited (average {}ms)",
+                   server, stats.active, stats.queue_depth,
+                   stats.max_queue_depth, stats.waited, average_wait);
+        }
+        Ok(())
+    }))
+}
+
+/* Release the client's connection from the rate limiter when the
+ * connection is finished with. */
+struct ClientGuard {
+    rate_limiter: Arc<Mutex<RateLimiter>>,
+    address:      IpAddr,
+}
+
+impl Drop for ClientGuard {
+    fn drop(&mut self) {
+        self.rate_limiter.lock().unwrap().disconnect(self.address);
+    }
+}
+
+/* Return a response containing the given message, after the given
+ * delay. */
+fn delayed_message(message: String, delay: Duration, handle: &Handle)
+        -> QueryFuture {
+    let response = format!("% {}\n", message).into_bytes();
+    match Timeout::new(delay, handle) {
+        Ok(timeout) => Box::new(timeout.map(move |_| response)),
+        Err(e)      => Box::new(future::err(e))
+    }
+}
+
+/* Route the query line from the client to the appropriate server, and
+ * return the response.  Failures are converted into comments in the
+ * response. */
+fn handle_query(line: String, client_addr: SocketAddr, state: &State)
+        -> QueryFuture {
+    let decision = state.rate_limiter.lock().unwrap()
+                        .query(client_addr.ip(), Instant::now());
+    match decision {
+        Decision::Allow => {},
+        Decision::Reject(reason) => {
+            info!("'{}' from {} refused: {}", &line, client_addr, reason);
+            return delayed_message(format!("Query refused: {}", reason),
+                                   Duration::from_secs(0), &state.handle);
+        },
+        Decision::Tarpit(delay, reason) => {
+            info!("'{}' from {} refused after delay: {}",
+                  &line, client_addr, reason);
+            return delayed_message(format!("Query refused: {}", reason),
+                                   delay, &state.handle);
+        }
+    }
+
+    let mut query = Query::parse(&line);
+    let server;
+    let handled;
+    {
+        let inner_context = CONTEXT.lock().unwrap();
+        /* Queries for IPv6 addresses with an embedded IPv4 address are
+         * sent upstream as queries for the IPv4 address. */
+        if let Some(ipv4_net) = inner_context.embedded_ipv4(&query.resource) {
+            let resource =
+                match ipv4_net.prefix_len() {
+                    32 => ipv4_net.addr().to_string(),
+                    _  => ipv4_net.to_string()
+                };
+            info!("'{}' from {} rewritten to '{}'",
+                  &line, client_addr, resource);
+            query = query.with_resource(&resource);
+        }
+        /* Internationalised domain names are sent upstream in their
+         * ASCII form. */
+        if !query.resource.is_ascii() {
+            if let Some(name) = parse_domain(&query.resource) {
+                info!("'{}' from {} rewritten to '{}'",
+                      &line, client_addr, name);
+                query = query.with_resource(&name);
+            }
+        }
+        let inner_server  = match inner_context.lookup(&query.resource) {
+            Some(server) => {
+                info!("'{}' from {} redirecting to {}",
+                      &line, client_addr, server);
+                handled = true;
+                server
+            },
+            None => {
+                let parts = fan_out_parts(&inner_context, &query.resource,
+                                          &state.config);
+                if let Some(parts) = parts {
+                    /* The policy applies to the query as a whole (as
+                     * though it were sent to the default server), as
+                     * well as to each part. */
+                    if !is_query_allowed(&query.resource, client_addr,
+                                         &state.config.default_server,
+                                         true, &state.config) {
+                        return refused_by_policy(&line, client_addr);
+                    }
+                    info!("'{}' from {} split across {} parts",
+                          &line, client_addr, parts.len());
+                    return query_parts(line, query, parts, client_addr,
+                                       state);
+                }
+                info!("'{}' from {} not handled, redirecting to {}",
+                      &line, client_addr, &state.config.default_server);
+                handled = false;
+                &state.config.default_server
+            }
         };
+        server = inner_server.to_string();
+    }
+    if !is_query_allowed(&query.resource, client_addr, &server, handled,
+                         &state.config) {
+        return refused_by_policy(&line, client_addr);
+    }
+    let server_ = server.clone();
+    Box::new(query_with_cache(server, query, state.clone())
+        .or_else(move |e| {
+            error!("'{}' from {} failed: {}", &line, client_addr, e);
+            let message =
+                format!("% Unable to query {}: {}\n", server_, e);
+            Ok(message.into_bytes())
+        }))
+}
+
+/* Return whether a query for the resource may be sent to the server,
+ * according to the query policy.  'handled' is whether the server was
+ * found in the mapping data.  For IP address ranges, each prefix in
+ * the range must be permitted. */
+fn is_query_allowed(resource: &str, client_addr: SocketAddr, server: &str,
+                    handled: bool, config: &Config) -> bool {
+    let client = client_addr.ip();
+    match parse_resources(resource) {
+        Some(resources) => {
+            resources.into_iter().all(|resource| {
+                config.query_policy.is_allowed(client, Some(resource),
+                                               server, handled)
+            })
+        },
+        None => config.query_policy.is_allowed(client, None, server, handled)
+    }
+}
+
+fn refused_by_policy(line: &str, client_addr: SocketAddr) -> QueryFuture {
+    info!("'{}' from {} refused by policy", line, client_addr);
+    Box::new(future::ok(b"% Query refused by policy\n".to_vec()))
+}
+
+/* Return the parts into which a query for the resource should be
+ * split, with the server for each part (if any), if the resource is
+ * split across servers.  If none of the parts is handled by a server,
+ * or if there are more parts than the configured maximum, then the
+ * query is not split. */
+fn fan_out_parts(context: &Context, resource: &str, config: &Config)
+        -> Option<Vec<(String, Option<String>)>> {
+    if config.max_fan_out == 0 {
+        return None;
+    }
+    let parts = context.lookup_parts(resource);
+    if parts.len() < 2 || parts.len() > config.max_fan_out
+            || parts.iter().all(|&(_, server)| server.is_none()) {
+        return None;
+    }
+    Some(parts.into_iter()
+        .map(|(part, server)| (part, server.map(|s| s.to_string())))
+        .collect())
+}
+
+/* Query each part of a resource that is split across servers, and
+ * merge the responses, in order, with a header before each.  Parts
+ * that are not handled by any server are sent to the default
+ * server. */
+fn query_parts(line: String, query: Query,
+               parts: Vec<(String, Option<String>)>,
+               client_addr: SocketAddr, state: &State) -> QueryFuture {
+    let futures: Vec<QueryFuture> =
+        parts.into_iter().map(|(part, server)| {
+            let handled = server.is_some();
+            let server = server.unwrap_or_else(|| {
+                state.config.default_server.clone()
+            });
+            let header =
+                format!("% Response for {} from {}:\n\n", part, server);
+            let resource = parse_resource(&part);
+            if !state.config.query_policy.is_allowed(client_addr.ip(),
+                                                     resource, &server,
+                                                     handled) {
+                info!("'{}' from {} refused by policy for {}",
+                      &line, client_addr, part);
+                let message =
+                    format!("{}% Query refused by policy\n", header);
+                return Box::new(future::ok(message.into_bytes()))
+                    as QueryFuture;
+            }
+            info!("'{}' from {} redirecting {} to {}",
+                  &line, client_addr, part, server);
+            let line = line.clone();
+            let part_query = query.with_resource(&part);
+            Box::new(query_with_cache(server.clone(), part_query,
+                                      state.clone())
+                .or_else(move |e| {
+                    error!("'{}' from {} failed for {}: {}",
+                           &line, client_addr, part, e);
+                    let message =
+                        format!("% Unable to query {}: {}\n", server, e);
+                    Ok(message.into_bytes())
+                })
+                .map(move |response| {
+                    let mut section = header.into_bytes();
+                    section.extend_from_slice(&response);
+                    section
+                })) as QueryFuture
+        }).collect();
+    Box::new(future::join_all(futures).map(|sections| {
+        sections.join(&b"\n"[..])
+    }))
+}
+
+/* Handle the remaining lines of a session.  The queries are handled
+ * in the same way as single queries, with up to 'bulk_concurrency'
+ * queries in progress at once for bulk sessions, and the responses
+ * are written in the order of the queries. */
+fn handle_session<S, W>(session: Session, lines: S, client_writer: W,
+                        client_addr: SocketAddr, state: &State)
+        -> Box<dyn Future<Item = W, Error = io::Error>>
+        where S: Stream<Item = Input, Error = io::Error> + 'static,
+              W: AsyncWrite + 'static {
+    let idle_timeout_message =
+        format!("no query received within {}s",
+                state.config.idle_timeout.as_secs());
+    let lines = match with_idle_timeout(lines, state.config.idle_timeout,
+                                        idle_timeout_message,
+                                        &state.handle) {
+        Ok(lines) => lines,
+        Err(e)    => return Box::new(future::err(e))
+    };
+    let concurrency = session.concurrency(state.config.bulk_concurrency);
+    let state_ = state.clone();
+    let responses = session_inputs(session, lines)
+        .map(move |item| -> QueryFuture {
+            match item {
+                Ok(Input::Query(line)) => {
+                    let (line, query) = session.query(line);
+                    Box::new(handle_query(line, client_addr, &state_)
+                        .map(move |response| {
+                            session_block(query.as_deref(), response)
+                        }))
+                },
+                Ok(Input::TooLong) => {
+                    info!("Query from {} refused: query too long",
+                          client_addr);
+                    Box::new(future::ok(
+                        session_block(None, b"% Query too long\n".to_vec())
+                    ))
+                },
+                Ok(Input::Invalid) => {
+                    info!("Query from {} refused: invalid characters",
+                          client_addr);
+                    Box::new(future::ok(
+                        session_block(None,
+                                      b"% Query contains invalid characters\n"
+                                          .to_vec())
+                    ))
+                },
+                Err(ref e) if e.kind() == io::ErrorKind::TimedOut => {
+                    info!("{} from {} timed out: {}",
+                          session.name(), client_addr, e);
+                    Box::new(future::ok(
+                        b"% Timed out waiting for query\n".to_vec()
+                    ))
+                },
+                Err(e) => Box::new(future::err(e))
+            }
+        })
+        .buffered(concurrency);
+    Box::new(responses.fold(client_writer, |client_writer, block| {
+        write_all(client_writer, block)
+            .map(|(client_writer, _)| client_writer)
+    }))
+}
+
+/* Write the message to the client, and close the connection. */
+fn write_message<W>(client_writer: W, message: &'static str)
+        -> Box<dyn Future<Item = (), Error = io::Error>>
+        where W: AsyncWrite + 'static {
+    Box::new(write_all(client_writer, message)
+        .and_then(|(client_writer, _)| shutdown(client_writer))
+        .map(|_| ()))
+}
+
+fn handle_client(client: Box<dyn Connection>, client_addr: SocketAddr, state: &State)
+        -> Box<dyn Future<Item = (), Error = io::Error>> {
+    let start_time = Instant::now();
+    let (client_reader, client_writer) = client.split();
+
+    if !state.config.access_list.is_allowed(client_addr.ip()) {
+        info!("Connection from {} refused: access denied", client_addr);
+        return write_message(client_writer, "% Access denied\n");
+    }
+
+    let decision = state.rate_limiter.lock().unwrap()
+                        .connect(client_addr.ip(), Instant::now());
+    let delay = match decision {
+        Decision::Allow => None,
+        Decision::Reject(reason) => {
+            info!("Connection from {} refused: {}", client_addr, reason);
+            let message = format!("% Connection refused: {}\n", reason);
+            return Box::new(write_all(client_writer, message)
+                .and_then(|(client_writer, _)| shutdown(client_writer))
+                .map(|_| ()));
+        },
+        Decision::Tarpit(delay, reason) => Some((delay, reason))
+    };
+    let guard = ClientGuard { rate_limiter: state.rate_limiter.clone(),
+                              address:      client_addr.ip() };
+    if let Some((delay, reason)) = delay {
+        info!("Connection from {} refused after delay: {}",
+              client_addr, reason);
+        return Box::new(
+            delayed_message(format!("Connection refused: {}", reason),
+                            delay, &state.handle)
+                .and_then(move |message| write_all(client_writer, message))
+                .and_then(move |(client_writer, _)| {
+                    drop(guard);
+                    shutdown(client_writer)
+                })
+                .map(|_| ())
+        );
+    }
+
+    let state_ = state.clone();
+    let idle_timeout_message =
+        format!("no query received within {}s",
+                state.config.idle_timeout.as_secs());
+    let first_input =
+        query_lines(client_reader, state.config.max_query_length)
+            .into_future()
+            .map_err(|e| e.0);
+    Box::new(with_timeout(first_input, state.config.idle_timeout,
+                          idle_timeout_message, &state.handle)
+        .then(move |result| -> Box<dyn Future<Item = (), Error = io::Error>> {
+            let (result, lines) = match result {
+                Ok((input, lines)) => (Ok(input), Some(lines)),
+                Err(e)             => (Err(e), None)
+            };
+            let input = match result {
+                Ok(Some(Input::Query(ref line)))
+                        if Session::start(line).is_some() => {
+                    let (session, first) = Session::start(line).unwrap();
+                    info!("{} from {}", session.name(), client_addr);
+                    let lines = stream::iter_ok(first).chain(lines.unwrap());
+                    return Box::new(
+                        handle_session(session, lines, client_writer,
+                                       client_addr, &state_)
+                            .and_then(move |client_writer| {
+                                let duration = Instant::now() - start_time;
+                                info!("{} from {} completed ({}ms)",
+                                      session.name(), client_addr,
+                                      duration_to_ms(duration));
+                                drop(guard);
+                                shutdown(client_writer).map(|_| ())
+                            })
+                    );
+                },
+                Ok(input) => input,
+                Err(ref e) if e.kind() == io::ErrorKind::TimedOut => {
+                    info!("Connection from {} timed out: {}", client_addr, e);
+                    return write_message(client_writer,
+                                         "% Timed out waiting for query\n");
+                },
+                Err(e) => return Box::new(future::err(e))
+            };
+            let line_data = match first_line(input) {
+                FirstLine::Query(line) => line,
+                FirstLine::Refused { reason, message } => {
+                    info!("Query from {} refused: {}", client_addr, reason);
+                    return write_message(client_writer, message);
+                },
+                FirstLine::Closed => {
+                    info!("Connection from {} closed without a query",
+                          client_addr);
+                    return Box::new(future::ok(()));
+                }
+            };
+            let line_data_original = line_data.clone();
+            Box::new(handle_query(line_data, client_addr, &state_)
+                .and_then(move |response| {
+                    write_all(client_writer, response)
+                }).and_then(move |(client_writer, _)| {
+                    let end_time = Instant::now();
+                    let duration = end_time - start_time;
+                    info!("'{}' from {} completed ({}ms)",
+                          &line_data_original, client_addr,
+                          duration_to_ms(duration));
+                    drop(guard);
+                    shutdown(client_writer).map(|_| ())
+                }))
+        }))
+}
+
+/* Handle the connections accepted by the listener.  If the
+ * connection comes from a trusted proxy, then the PROXY header is
+ * read first, and the client address from that header is used in
+ * place of the proxy's address.  If there is a TLS acceptor, then
+ * the TLS handshake is completed before the connection is handled. */
+fn serve(listener: Listener, tls: Option<TlsAcceptor>, state: &State)
+        -> Box<dyn Future<Item = (), Error = io::Error>> {
+    match tls {
+        None    => info!("Listening on {}", listener.address),
+        Some(_) => info!("Listening on {} (TLS)", listener.address)
+    }
+    let state = state.clone();
+    Box::new(listener.incoming.for_each(move |(client, client_addr)| {
+        let handle = state.handle.clone();
+        let connection: Box<dyn Future<Item = (Box<dyn Connection>, SocketAddr),
+                                       Error = io::Error>> =
+            if proxy::is_trusted(&state.config.proxy_protocol,
+                                 client_addr.ip()) {
+                let header =
+                    with_timeout(read_proxy_header(client),
+                                 state.config.idle_timeout,
+                                 "PROXY header timed out".to_owned(),
+                                 &handle);
+                Box::new(header.map(move |(client, address)| {
+                    let address = address.unwrap_or(client_addr);
+                    debug!("Connection from {} via proxy {}",
+                           address, client_addr);
+                    (client, address)
+                }))
+            } else {
+                Box::new(future::ok((client, client_addr)))
+            };
 
+        let state_ = state.clone();
+        let tls_ = tls.clone();
+        let handler = connection.map_err(move |e| (e, client_addr))
+                                .and_then(move |(client, client_addr)| {
+            let state = state_;
+            let handler: Box<dyn Future<Item = (), Error = io::Error>> =
+                match tls_ {
+                    None => handle_client(client, client_addr, &state),
+                    Some(acceptor) => {
+                        let accept =
+                            with_timeout(acceptor.accept(client),
+                                         state.config.idle_timeout,
+                                         "TLS handshake timed out".to_owned(),
+                                         &state.handle);
+                        Box::new(accept.and_then(move |client| {
+                            handle_client(Box::new(client), client_addr, &state)
+                        }))
+                    }
+                };
+            handler.map_err(move |e| (e, client_addr))
+        });
+        handle.spawn(handler.map_err(move |(e, client_addr)| {
+            info!("Connection from {} failed: {}", client_addr, e);
+        }));
+        Ok(())
+    }))
+}
+
+pub fn run(config: Config) {
     let mut core = Core::new().unwrap();
     let handle = core.handle();
 
+    let state = State {
+        cache:        Arc::new(Mutex::new(ResponseCache::new(config.cache))),
+        rate_limiter: Arc::new(Mutex::new(RateLimiter::new(config.rate_limit))),
+        resolver:     Resolver::new(config.resolver.clone(), &handle),
+        config:       Arc::new(config),
+        in_flight:    Coalescer::new(),
+        limiter:      Limiter::new(),
+        tls_connectors: TlsConnectors::new(),
+        sessions:     SessionPool::new(),
+        handle:       handle.clone(),
+    };
+
     info!("Loading data");
     {
+        let mut context = CONTEXT.lock().unwrap();
+        context.embedded_ipv4 = state.config.embedded_ipv4;
         let _unused =
-            CONTEXT.lock().unwrap().ipv4.get_longest_match(
+            context.ipv4.get_longest_match(
                 Ipv4Net::from_str("0.0.0.0/32").unwrap()
             );
     }
@@ -112,69 +1022,37 @@ pub fn run(default_server_option: Option<String>,
         let _unused = watch();
     });
 
-    let addr = format!("0.0.0.0:{}", port).parse().unwrap();
-    let tcp_listener = TcpListener::bind(&addr, &handle).unwrap();
-    info!("Listening on port {}", port);
-
-    let server = tcp_listener.incoming().for_each(move |(client, client_addr)| {
-        let start_time = Instant::now();
-        let (client_reader, client_writer) = client.split();
-        let buf_reader = BufReader::new(client_reader);
-        let default_server_ = default_server.to_owned();
-        let handle_inner = handle.clone();
-        let handler = lines(buf_reader)
-                .into_future()
-                .map_err(|e| e.0).
-                and_then(move |(line, _)| {
-            let mut line_data = line.unwrap();
-            let line_data_original = line_data.clone();
-            let server;
-            {
-                let inner_context = CONTEXT.lock().unwrap();
-                let inner_server  = match inner_context.lookup(&line_data) {
-                    Some(server) => {
-                        info!("'{}' from {} redirecting to {}",
-                              &line_data, client_addr, server);
-                        server
-                    },
-                    None => {
-                        info!("'{}' from {} not handled, redirecting to {}",
-                              &line_data, client_addr, &default_server_);
-                        &default_server_
-                    }
-                };
-                server = inner_server.to_string();
-            }
-            let mut server_spec = server.to_string();
-            server_spec.push_str(":43");
-            let target: &str = &server_spec;
-            let socket_addr =
-                target.to_socket_addrs().unwrap().next().unwrap();
-            let server = TcpStream::connect(&socket_addr, &handle_inner);
-            server.and_then(move |server| {
-                let (server_reader, server_writer) = server.split();
-                line_data.push_str("\r\n");
-
-                write_all(server_writer, line_data).and_then(move |(socket, _)| {
-                    copy(server_reader, client_writer)
-                        .and_then(move |(n, _, client_writer)| {
-                            let end_time = Instant::now();
-                            let duration = end_time - start_time;
-                            info!("'{}' from {} completed ({}ms)",
-                                  &line_data_original, client_addr,
-                                  duration_to_ms(duration));
-
-                            shutdown(client_writer).map(move |_| n).join(
-                                shutdown(socket).map(move |_| n))
-                        })
-                })
-            })
+    handle.spawn(log_limiter_stats(state.limiter.clone(), &handle)
+                     .map_err(|e| error!("Unable to log connection statistics: {}", e)));
+
+    let listen_addresses =
+        if state.config.listen.is_empty() {
+            let addr = format!("0.0.0.0:{}", state.config.port).parse().unwrap();
+            vec![ListenAddress::Tcp(addr)]
+        } else {
+            state.config.listen.clone()
+        };
+    let mut servers: Vec<_> =
+        listener::bind(&listen_addresses, &handle).unwrap()
+            .into_iter()
+            .map(|listener| serve(listener, None, &state))
+            .collect();
+
+    if let Some(ref tls_config) = state.config.tls {
+        let acceptor = TlsAcceptor::new(tls_config).unwrap();
+        let tls_config_ = tls_config.clone();
+        let acceptor_ = acceptor.clone();
+        thread::spawn(move || {
+            let _unused = watch_tls(tls_config_, acceptor_);
         });
-        handle.spawn(handler.map(|_| {}).map_err(|_| {}));
-        Ok(())
-    });
+        servers.extend(
+            listener::bind(&tls_config.listen, &handle).unwrap()
+                .into_iter()
+                .map(|listener| serve(listener, Some(acceptor.clone()), &state))
+        );
+    }
 
-    core.run(server).unwrap();
+    core.run(future::join_all(servers)).unwrap();
 }
 
 /* Taken from tokio-core/examples/proxy.rs. */
@@ -0,0 +1,187 @@
+extern crate futures;
+extern crate tokio_core;
+
+use super::input::Input;
+
+use self::futures::{Async, Future, Poll, Stream};
+use self::tokio_core::reactor::{Handle, Timeout};
+
+use std::io;
+use std::time::{Duration, Instant};
+
+/* Sessions in which the client sends multiple queries on a single
+ * connection.  In a bulk session, the client sends 'begin', followed
+ * by one query per line, followed by 'end', and queries are handled
+ * concurrently.  In a keep-alive session, the client sends '-k' (as
+ * for the RIPE-database-derived servers), either alone or before its
+ * first query, and queries are handled one at a time until the client
+ * sends '-k' again or closes the connection. */
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Session {
+    Bulk,
+    KeepAlive,
+}
+
+impl Session {
+    /* Return the session started by the client's first line, along
+     * with the first query of the session, if the line includes
+     * one. */
+    pub fn start(line: &str) -> Option<(Session, Option<Input>)> {
+        if is_bulk_begin(line) {
+            return Some((Session::Bulk, None));
+        }
+        keep_alive_query(line).map(|query| {
+            (Session::KeepAlive, Some(Input::Query(query.to_owned())))
+        })
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Session::Bulk      => "Bulk request",
+            Session::KeepAlive => "Persistent connection",
+        }
+    }
+
+    /* Return the number of queries that may be in progress at once. */
+    pub fn concurrency(self, bulk_concurrency: usize) -> usize {
+        match self {
+            Session::Bulk      => bulk_concurrency.max(1),
+            Session::KeepAlive => 1,
+        }
+    }
+
+    pub fn is_end(self, input: &Input) -> bool {
+        let line = match *input {
+            Input::Query(ref line) => line.trim(),
+            _                      => return false
+        };
+        match self {
+            Session::Bulk      => line.eq_ignore_ascii_case("end"),
+            Session::KeepAlive => keep_alive_query(line) == Some(""),
+        }
+    }
+
+    /* Return the query to be handled for the line, along with the
+     * query to include in the response block, if any (see
+     * session_block).  In keep-alive sessions, the client may repeat
+     * the keep-alive flag before each query. */
+    pub fn query(self, line: String) -> (String, Option<String>) {
+        match self {
+            Session::Bulk => {
+                let query = line.trim().to_owned();
+                (line, Some(query))
+            },
+            Session::KeepAlive => {
+                let line = keep_alive_query(&line)
+                    .map(|query| query.to_owned())
+                    .unwrap_or(line);
+                (line, None)
+            }
+        }
+    }
+}
+
+pub fn is_bulk_begin(line: &str) -> bool {
+    line.trim().eq_ignore_ascii_case("begin")
+}
+
+/* If the line starts with the keep-alive flag, return the rest of the
+ * line. */
+pub fn keep_alive_query(line: &str) -> Option<&str> {
+    let line = line.trim();
+    let rest = line.trim_start_matches(|c: char| !c.is_whitespace());
+    match &line[..line.len() - rest.len()] {
+        "-k" | "--persistent-connection" => Some(rest.trim()),
+        _                                => None
+    }
+}
+
+/* Return the block for a single response in a session.  The query is
+ * included in bulk sessions, since the client may not otherwise be
+ * able to tell which query the response is for. */
+pub fn session_block(query: Option<&str>, mut response: Vec<u8>) -> Vec<u8> {
+    let mut block = match query {
+        Some(query) => format!("% Query: {}\n", query).into_bytes(),
+        None        => Vec::new()
+    };
+    if !response.is_empty() && !response.ends_with(b"\n") {
+        response.push(b'\n');
+    }
+    block.extend(response);
+    block.extend_from_slice(b"% End of response\n");
+    block
+}
+
+/* Return the inputs of the session, up to (but not including) the
+ * input that ends the session, with blank lines removed.  Errors are
+ * passed through as items, so that a timeout can be reported to the
+ * client. */
+pub fn session_inputs<S>(session: Session, lines: S)
+        -> Box<dyn Stream<Item = io::Result<Input>, Error = io::Error>>
+        where S: Stream<Item = Input, Error = io::Error> + 'static {
+    Box::new(lines
+        .then(Ok::<_, io::Error>)
+        .take_while(move |item| {
+            match *item {
+                Ok(ref input) => Ok(!session.is_end(input)),
+                Err(_)        => Ok(true)
+            }
+        })
+        .filter(|item| {
+            match *item {
+                Ok(Input::Query(ref line)) => !line.trim().is_empty(),
+                _                          => true
+            }
+        }))
+}
+
+/* A stream that fails with a 'timed out' error if no item is received
+ * within the given duration of the stream being polled for one, and
+ * then ends.  The duration only runs while the stream is waiting for
+ * its next item, so time spent by the consumer between items is not
+ * counted. */
+pub struct IdleTimeout<S> {
+    stream:    S,
+    timeout:   Timeout,
+    duration:  Duration,
+    waiting:   bool,
+    timed_out: bool,
+    message:   String,
+}
+
+pub fn with_idle_timeout<S>(stream: S, duration: Duration, message: String,
+                            handle: &Handle) -> io::Result<IdleTimeout<S>>
+        where S: Stream<Error = io::Error> {
+    Ok(IdleTimeout { stream, timeout: Timeout::new(duration, handle)?,
+                     duration, waiting: false, timed_out: false, message })
+}
+
+impl<S> Stream for IdleTimeout<S> where S: Stream<Error = io::Error> {
+    type Item = S::Item;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Option<S::Item>, io::Error> {
+        if self.timed_out {
+            return Ok(Async::Ready(None));
+        }
+        match self.stream.poll()? {
+            Async::Ready(item) => {
+                self.waiting = false;
+                return Ok(Async::Ready(item));
+            },
+            Async::NotReady => {}
+        }
+        if !self.waiting {
+            self.waiting = true;
+            self.timeout.reset(Instant::now() + self.duration);
+        }
+        match self.timeout.poll()? {
+            Async::Ready(()) => {
+                self.timed_out = true;
+                Err(io::Error::new(io::ErrorKind::TimedOut,
+                                   self.message.clone()))
+            },
+            Async::NotReady => Ok(Async::NotReady)
+        }
+    }
+}
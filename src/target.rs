@@ -0,0 +1,137 @@
+use std::fmt;
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use std::str::FromStr;
+
+/* Upstream server targets, as used in the mapping data and in
+ * referrals.  A target may be written in any of the following forms:
+ *
+ *     whois.example.net
+ *     whois.example.net:4343
+ *     192.0.2.1:4343
+ *     2001:db8::1
+ *     [2001:db8::1]:4343
+ *     whois://whois.example.net:4343/
+ *     rwhois://rwhois.example.net:4321
+ *     whois+tls://whois.example.net:4343
+ *
+ * The port defaults to 43 for Whois and 4321 for RWhois.  A '+tls'
+ * suffix on the scheme means that the server is queried over TLS (as
+ * with the 'tls' upstream option).  Each target
+ * also has a canonical string form, which is used to identify the
+ * server elsewhere (e.g. in the upstream configuration, and for
+ * caching and connection limits). */
+
+pub const WHOIS_PORT:  u16 = 43;
+pub const RWHOIS_PORT: u16 = 4321;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum UpstreamProtocol {
+    Whois,
+    Rwhois,
+}
+
+impl UpstreamProtocol {
+    pub fn default_port(self) -> u16 {
+        match self {
+            UpstreamProtocol::Whois  => WHOIS_PORT,
+            UpstreamProtocol::Rwhois => RWHOIS_PORT,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct UpstreamTarget {
+    pub protocol: UpstreamProtocol,
+    pub host:     String,
+    pub port:     u16,
+    pub tls:      bool,
+}
+
+fn parse_host_port(value: &str, default_port: u16)
+        -> Result<(String, u16), String> {
+    if value.is_empty() {
+        return Err("empty host".to_owned());
+    }
+    if let Ok(address) = Ipv6Addr::from_str(value) {
+        return Ok((address.to_string(), default_port));
+    }
+    if let Ok(socket_addr) = SocketAddr::from_str(value) {
+        return Ok((socket_addr.ip().to_string(), socket_addr.port()));
+    }
+    if value.starts_with('[') {
+        return Err(format!("invalid address: {}", value));
+    }
+    let mut parts = value.splitn(2, ':');
+    let host = parts.next().unwrap();
+    let port = match parts.next() {
+        Some(port) => {
+            u16::from_str(port).map_err(|_| format!("invalid port: {}", port))?
+        },
+        None => default_port
+    };
+    if host.is_empty() || host.contains(|c: char| c.is_whitespace() || c == '/') {
+        return Err(format!("invalid host: {}", host));
+    }
+    Ok((host.to_lowercase(), port))
+}
+
+impl FromStr for UpstreamTarget {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<UpstreamTarget, String> {
+        let value = value.trim();
+        let (protocol, tls, rest) =
+            match value.find("://") {
+                Some(index) => {
+                    let scheme = value[..index].to_lowercase();
+                    let (scheme, tls) =
+                        match scheme.strip_suffix("+tls") {
+                            Some(scheme) => (scheme.to_owned(), true),
+                            None         => (scheme, false)
+                        };
+                    let protocol =
+                        match scheme.as_str() {
+                            "whois"  => UpstreamProtocol::Whois,
+                            "rwhois" => UpstreamProtocol::Rwhois,
+                            scheme   => {
+                                return Err(format!("unsupported scheme: {}",
+                                                   scheme));
+                            }
+                        };
+                    (protocol, tls, value[index + 3..].trim_end_matches('/'))
+                },
+                None => (UpstreamProtocol::Whois, false, value)
+            };
+        let (host, port) = parse_host_port(rest, protocol.default_port())?;
+        Ok(UpstreamTarget { protocol, host, port, tls })
+    }
+}
+
+impl fmt::Display for UpstreamTarget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.protocol, self.tls) {
+            (UpstreamProtocol::Whois,  false) => {},
+            (UpstreamProtocol::Whois,  true)  => write!(f, "whois+tls://")?,
+            (UpstreamProtocol::Rwhois, false) => write!(f, "rwhois://")?,
+            (UpstreamProtocol::Rwhois, true)  => write!(f, "rwhois+tls://")?,
+        }
+        let is_ipv6 = matches!(IpAddr::from_str(&self.host),
+                               Ok(IpAddr::V6(_)));
+        if self.port == self.protocol.default_port() {
+            write!(f, "{}", self.host)
+        } else if is_ipv6 {
+            write!(f, "[{}]:{}", self.host, self.port)
+        } else {
+            write!(f, "{}:{}", self.host, self.port)
+        }
+    }
+}
+
+/* Return the canonical form of the server name, or the name itself if
+ * it cannot be parsed. */
+pub fn canonical_server(server: &str) -> String {
+    match UpstreamTarget::from_str(server) {
+        Ok(target) => target.to_string(),
+        Err(_)     => server.to_owned()
+    }
+}
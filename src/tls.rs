@@ -0,0 +1,152 @@
+extern crate futures;
+extern crate openssl;
+extern crate tokio_io;
+extern crate tokio_openssl;
+
+use super::listener::ListenAddress;
+
+use self::futures::{future, Future};
+use self::openssl::error::ErrorStack;
+use self::openssl::ssl::{SslAcceptor, SslConnector, SslFiletype, SslMethod,
+                         SslVerifyMode};
+use self::openssl::x509::{X509, X509Name};
+use self::openssl::x509::store::X509StoreBuilder;
+use self::tokio_io::{AsyncRead, AsyncWrite};
+use self::tokio_openssl::{SslAcceptorExt, SslConnectorExt, SslStream};
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+
+/* Whois over TLS.  For the listener, the certificate and key are
+ * loaded from PEM files, and may be reloaded while the server is
+ * running.  If a client CA file is configured, then clients must
+ * present a certificate signed by one of the CAs in that file.  For
+ * upstream servers, the server's certificate is verified against
+ * either the system's trusted CAs or the CAs in a given file. */
+
+#[derive(Clone, Debug)]
+pub struct TlsConfig {
+    /* The addresses to listen on for TLS connections. */
+    pub listen:      Vec<ListenAddress>,
+    /* The certificate chain, with the server's certificate first. */
+    pub certificate: PathBuf,
+    pub key:         PathBuf,
+    pub client_ca:   Option<PathBuf>,
+}
+
+pub fn build_acceptor(config: &TlsConfig) -> Result<SslAcceptor, ErrorStack> {
+    let mut builder =
+        SslAcceptor::mozilla_intermediate_v5(SslMethod::tls())?;
+    builder.set_certificate_chain_file(&config.certificate)?;
+    builder.set_private_key_file(&config.key, SslFiletype::PEM)?;
+    builder.check_private_key()?;
+    if let Some(ref client_ca) = config.client_ca {
+        builder.set_ca_file(client_ca)?;
+        builder.set_client_ca_list(X509Name::load_client_ca_file(client_ca)?);
+        builder.set_verify(SslVerifyMode::PEER
+                           | SslVerifyMode::FAIL_IF_NO_PEER_CERT);
+    }
+    Ok(builder.build())
+}
+
+/* An acceptor for TLS connections, which can be shared between the
+ * reactor thread and the thread that reloads the certificate. */
+#[derive(Clone)]
+pub struct TlsAcceptor {
+    acceptor: Arc<Mutex<SslAcceptor>>,
+}
+
+impl TlsAcceptor {
+    pub fn new(config: &TlsConfig) -> Result<TlsAcceptor, ErrorStack> {
+        let acceptor = build_acceptor(config)?;
+        Ok(TlsAcceptor { acceptor: Arc::new(Mutex::new(acceptor)) })
+    }
+
+    /* Reload the certificate and key.  If they cannot be loaded, then
+     * the existing certificate and key continue to be used. */
+    pub fn reload(&self, config: &TlsConfig) -> Result<(), ErrorStack> {
+        let acceptor = build_acceptor(config)?;
+        *self.acceptor.lock().unwrap() = acceptor;
+        Ok(())
+    }
+
+    /* Perform the TLS handshake on the stream. */
+    pub fn accept<S>(&self, stream: S)
+            -> Box<dyn Future<Item = SslStream<S>, Error = io::Error>>
+            where S: AsyncRead + AsyncWrite + 'static {
+        let acceptor = self.acceptor.lock().unwrap().clone();
+        Box::new(acceptor.accept_async(stream).map_err(|e| {
+            io::Error::other(format!("TLS handshake failed: {}", e))
+        }))
+    }
+}
+
+/* Build a connector for TLS connections to upstream servers.  If a CA
+ * file is given, then only the CAs in that file are trusted.
+ * Otherwise, the system's trusted CAs are used. */
+pub fn build_connector(ca_file: Option<&Path>) -> io::Result<SslConnector> {
+    let to_io_error = |e: ErrorStack| io::Error::other(e.to_string());
+    let mut builder = SslConnector::builder(SslMethod::tls())
+        .map_err(to_io_error)?;
+    if let Some(ca_file) = ca_file {
+        let certificates = X509::stack_from_pem(&fs::read(ca_file)?)
+            .map_err(to_io_error)?;
+        let mut store = X509StoreBuilder::new().map_err(to_io_error)?;
+        for certificate in certificates {
+            store.add_cert(certificate).map_err(to_io_error)?;
+        }
+        builder.set_cert_store(store.build());
+    }
+    Ok(builder.build())
+}
+
+/* Connectors for TLS connections to upstream servers, keyed by CA
+ * file, so that CA files are only loaded once.  This is only for use
+ * on the reactor thread. */
+#[derive(Clone, Default)]
+pub struct TlsConnectors {
+    connectors: Rc<RefCell<HashMap<Option<PathBuf>, SslConnector>>>,
+}
+
+impl TlsConnectors {
+    pub fn new() -> TlsConnectors {
+        TlsConnectors::default()
+    }
+
+    /* Perform the TLS handshake on the stream, verifying that the
+     * server's certificate is valid for the host. */
+    pub fn connect<S>(&self, host: &str, ca_file: Option<&Path>, stream: S)
+            -> Box<dyn Future<Item = SslStream<S>, Error = io::Error>>
+            where S: AsyncRead + AsyncWrite + 'static {
+        let key = ca_file.map(|path| path.to_path_buf());
+        let existing = self.connectors.borrow().get(&key).cloned();
+        let connector = match existing {
+            Some(connector) => connector,
+            None => {
+                match build_connector(ca_file) {
+                    Ok(connector) => {
+                        self.connectors.borrow_mut()
+                            .insert(key, connector.clone());
+                        connector
+                    },
+                    Err(e) => {
+                        return Box::new(future::err(io::Error::new(
+                            e.kind(),
+                            format!("unable to load TLS CA file: {}", e)
+                        )));
+                    }
+                }
+            }
+        };
+        let host_ = host.to_owned();
+        Box::new(connector.connect_async(host, stream).map_err(move |e| {
+            io::Error::other(format!("TLS handshake with {} failed: {}",
+                                     host_, e))
+        }))
+    }
+}
@@ -0,0 +1,209 @@
+extern crate csv;
+
+use super::query::{Query, QueryStyle, query_style};
+use super::target::canonical_server;
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+/* Per-server settings for upstream Whois servers.  These are loaded
+ * from a CSV file where each line contains a server name (as used in
+ * the mapping data), an option name, and the option's value(s):
+ *
+ *     whois.arin.net,template,n + {query}
+ *     whois.nic.ad.jp,template,{query}/e
+ *     whois.ripe.net,style,ripe
+ *     whois.ripe.net,flag,-b,-B
+ *     whois.example.net,connect-timeout,2
+ *     whois.example.net,tls,on
+ *     whois.example.net,tls-ca-file,/etc/owhois/ca.pem
+ *     whois.ripe.net,keep-alive,on
+ *     whois.ripe.net,pool-size,8
+ */
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Timeouts {
+    /* The time allowed for establishing the connection. */
+    pub connect:    Duration,
+    /* The time allowed between sending the query and receiving the
+     * first part of the response. */
+    pub first_byte: Duration,
+    /* The time allowed for the entire exchange with the server. */
+    pub total:      Duration,
+}
+
+impl Default for Timeouts {
+    fn default() -> Timeouts {
+        Timeouts {
+            connect:    Duration::from_secs(5),
+            first_byte: Duration::from_secs(15),
+            total:      Duration::from_secs(30),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct UpstreamOptions {
+    /* The query style for the server, if it differs from the default
+     * style for the server (see query::query_style). */
+    pub style:    Option<QueryStyle>,
+    /* A template for the query, where '{query}' is replaced with the
+     * translated query.  The template is only used when the client's
+     * query does not include any flags or keywords of its own. */
+    pub template: Option<String>,
+    /* Flag substitutions, as (from, to) pairs.  If the replacement is
+     * empty, then the flag is dropped. */
+    pub flags:    Vec<(String, String)>,
+    /* Timeouts for the server, if they differ from the defaults. */
+    pub connect_timeout:    Option<Duration>,
+    pub first_byte_timeout: Option<Duration>,
+    pub total_timeout:      Option<Duration>,
+    /* The maximum number of concurrent connections to the server,
+     * and the time for which a query will wait for a connection, if
+     * they differ from the defaults. */
+    pub max_connections:    Option<usize>,
+    pub queue_timeout:      Option<Duration>,
+    /* Whether to connect to the server using TLS, and the file
+     * containing the CAs used to verify the server's certificate (if
+     * not set, then the system's trusted CAs are used). */
+    pub tls:                bool,
+    pub tls_ca_file:        Option<PathBuf>,
+    /* Whether the server supports persistent ('-k') connections, in
+     * which case queries are sent over a pool of such connections,
+     * and the maximum number of idle connections to keep in the pool
+     * (see pool.rs). */
+    pub keep_alive:         bool,
+    pub pool_size:          Option<usize>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct UpstreamConfig {
+    servers:  HashMap<String, UpstreamOptions>,
+    defaults: UpstreamOptions,
+}
+
+fn parse_seconds(value: &str) -> Duration {
+    Duration::from_secs(u64::from_str(value).unwrap())
+}
+
+fn parse_switch(value: &str) -> bool {
+    match value {
+        "on"  => true,
+        "off" => false,
+        _     => panic!("invalid switch value: {}", value)
+    }
+}
+
+fn parse_style(value: &str) -> QueryStyle {
+    match value {
+        "ripe"  => QueryStyle::Ripe,
+        "arin"  => QueryStyle::Arin,
+        "plain" => QueryStyle::Plain,
+        _       => panic!("invalid query style: {}", value)
+    }
+}
+
+impl UpstreamConfig {
+    pub fn from_file(path: &str) -> UpstreamConfig {
+        let file = File::open(path).unwrap();
+        let mut csv_reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(file);
+        let mut config = UpstreamConfig::default();
+        csv_reader.records()
+            .map(|i| i.unwrap())
+            .filter(|i| i.len() >= 3)
+            .for_each(|i| {
+                let server = canonical_server(i.get(0).unwrap());
+                let option = i.get(1).unwrap();
+                let value  = i.get(2).unwrap();
+                let options = config.servers.entry(server).or_default();
+                match option {
+                    "style"    => { options.style = Some(parse_style(value)) },
+                    "template" => { options.template = Some(value.to_owned()) },
+                    "flag"     => {
+                        let replacement = i.get(3).unwrap_or("");
+                        options.flags.push((value.to_owned(),
+                                            replacement.to_owned()));
+                    },
+                    "connect-timeout" => {
+                        options.connect_timeout = Some(parse_seconds(value))
+                    },
+                    "first-byte-timeout" => {
+                        options.first_byte_timeout = Some(parse_seconds(value))
+                    },
+                    "total-timeout" => {
+                        options.total_timeout = Some(parse_seconds(value))
+                    },
+                    "max-connections" => {
+                        options.max_connections =
+                            Some(usize::from_str(value).unwrap())
+                    },
+                    "queue-timeout" => {
+                        options.queue_timeout = Some(parse_seconds(value))
+                    },
+                    "tls" => { options.tls = parse_switch(value) },
+                    "tls-ca-file" => {
+                        options.tls_ca_file = Some(PathBuf::from(value))
+                    },
+                    "keep-alive" => { options.keep_alive = parse_switch(value) },
+                    "pool-size" => {
+                        options.pool_size = Some(usize::from_str(value).unwrap())
+                    },
+                    _ => panic!("invalid upstream option: {}", option)
+                }
+            });
+        config
+    }
+
+    pub fn get(&self, server: &str) -> &UpstreamOptions {
+        self.servers.get(&canonical_server(server)).unwrap_or(&self.defaults)
+    }
+
+    /* Return the timeouts for the given server, using the default
+     * timeouts for those that are not set for the server. */
+    pub fn timeouts(&self, server: &str, defaults: Timeouts) -> Timeouts {
+        let options = self.get(server);
+        Timeouts {
+            connect:    options.connect_timeout.unwrap_or(defaults.connect),
+            first_byte: options.first_byte_timeout.unwrap_or(defaults.first_byte),
+            total:      options.total_timeout.unwrap_or(defaults.total),
+        }
+    }
+
+    /* Return the query that should be sent to the given server. */
+    pub fn rewrite(&self, server: &str, query: &Query) -> String {
+        let options = self.get(server);
+        let style = options.style.unwrap_or_else(|| query_style(server));
+
+        let mut query_ = query.clone();
+        if !options.flags.is_empty() {
+            query_.flags = query.flags.iter().filter_map(|flag| {
+                match options.flags.iter().find(|&(from, _)| *from == flag.name) {
+                    Some((_, to)) if to.is_empty() => None,
+                    Some((_, to)) => {
+                        let mut flag_ = flag.clone();
+                        flag_.name = to.clone();
+                        Some(flag_)
+                    },
+                    None => Some(flag.clone())
+                }
+            }).collect();
+            if !query_.has_options() {
+                query_.original = query_.resource.clone();
+            }
+        }
+        let translated = query_.translate(style);
+
+        match options.template {
+            Some(ref template) if !query.has_options() => {
+                template.replace("{query}", &translated)
+            },
+            _ => translated
+        }
+    }
+}
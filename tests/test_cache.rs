@@ -0,0 +1,139 @@
+extern crate owhois;
+
+#[cfg(test)]
+mod test_cache {
+    use owhois::cache::{CacheConfig, ResponseCache, is_cacheable, is_negative};
+    use std::time::{Duration, Instant};
+
+    fn config() -> CacheConfig {
+        CacheConfig {
+            ttl:          Duration::from_secs(60),
+            negative_ttl: Duration::from_secs(10),
+            max_size:     100,
+            serve_stale:  false,
+        }
+    }
+
+    #[test]
+    fn cache_disabled() {
+        let mut cache = ResponseCache::new(CacheConfig::default());
+        let now = Instant::now();
+        assert!(!cache.is_enabled());
+        cache.insert("192.0.2.1", "whois.arin.net", b"data".to_vec(), now);
+        assert_eq!(cache.get("192.0.2.1", "whois.arin.net", now), None);
+    }
+
+    #[test]
+    fn cache_ttl() {
+        let mut cache = ResponseCache::new(config());
+        let now = Instant::now();
+        cache.insert("192.0.2.1", "whois.arin.net", b"data".to_vec(), now);
+        cache.insert("192.0.2.2", "whois.arin.net",
+                     b"No entries found".to_vec(), now);
+
+        assert_eq!(cache.get(" 192.0.2.1 ", "WHOIS.ARIN.NET", now),
+                   Some(b"data".to_vec()));
+        assert_eq!(cache.get("192.0.2.1", "whois.ripe.net", now), None);
+
+        let later = now + Duration::from_secs(30);
+        assert_eq!(cache.get("192.0.2.1", "whois.arin.net", later),
+                   Some(b"data".to_vec()));
+        assert_eq!(cache.get("192.0.2.2", "whois.arin.net", later), None);
+
+        let later = now + Duration::from_secs(61);
+        assert_eq!(cache.get("192.0.2.1", "whois.arin.net", later), None);
+        assert_eq!(cache.get_stale("192.0.2.1", "whois.arin.net"), None);
+    }
+
+    #[test]
+    fn cache_stale() {
+        let mut config = config();
+        config.serve_stale = true;
+        let mut cache = ResponseCache::new(config);
+        let now = Instant::now();
+        cache.insert("192.0.2.1", "whois.arin.net", b"data".to_vec(), now);
+
+        let later = now + Duration::from_secs(61);
+        assert_eq!(cache.get("192.0.2.1", "whois.arin.net", later), None);
+        assert_eq!(cache.get_stale("192.0.2.1", "whois.arin.net"),
+                   Some(b"data".to_vec()));
+    }
+
+    #[test]
+    fn cache_eviction() {
+        let mut cache = ResponseCache::new(config());
+        let now = Instant::now();
+        let response = vec![b'x'; 30];
+        cache.insert("a", "s", response.clone(), now);
+        cache.insert("b", "s", response.clone(), now);
+        cache.insert("c", "s", response.clone(), now);
+        assert_eq!(cache.size(), 96);
+
+        /* Using 'a' means that 'b' is the least-recently-used entry. */
+        assert!(cache.get("a", "s", now).is_some());
+        cache.insert("d", "s", response.clone(), now);
+        assert!(cache.get("a", "s", now).is_some());
+        assert!(cache.get("b", "s", now).is_none());
+        assert!(cache.get("c", "s", now).is_some());
+        assert!(cache.get("d", "s", now).is_some());
+        assert_eq!(cache.size(), 96);
+
+        /* Entries larger than the cache are not stored. */
+        cache.insert("e", "s", vec![b'x'; 200], now);
+        assert!(cache.get("e", "s", now).is_none());
+        assert_eq!(cache.size(), 96);
+    }
+
+    #[test]
+    fn cache_partial() {
+        let mut cache = ResponseCache::new(config());
+        let now = Instant::now();
+        cache.insert_partial("192.0.2.1", "whois.arin.net",
+                             b"data".to_vec(), now);
+
+        let later = now + Duration::from_secs(5);
+        assert_eq!(cache.get("192.0.2.1", "whois.arin.net", later),
+                   Some(b"data".to_vec()));
+        let later = now + Duration::from_secs(11);
+        assert_eq!(cache.get("192.0.2.1", "whois.arin.net", later), None);
+
+        /* A complete response replaces the partial one, and is cached
+         * for the full TTL. */
+        cache.insert("192.0.2.1", "whois.arin.net",
+                     b"complete data".to_vec(), now);
+        assert_eq!(cache.get("192.0.2.1", "whois.arin.net", later),
+                   Some(b"complete data".to_vec()));
+    }
+
+    #[test]
+    fn cache_uncacheable() {
+        let mut cache = ResponseCache::new(config());
+        let now = Instant::now();
+        let responses: [&[u8]; 4] = [
+            b"",
+            b"\r\n\n",
+            b"%ERROR:201: access denied for 192.0.2.1\n",
+            b"# Query rate limit exceeded\n",
+        ];
+        for response in responses.iter() {
+            assert!(!is_cacheable(response));
+            cache.insert("192.0.2.1", "whois.arin.net", response.to_vec(),
+                         now);
+            assert_eq!(cache.get("192.0.2.1", "whois.arin.net", now), None);
+            cache.insert_partial("192.0.2.1", "whois.arin.net",
+                                 response.to_vec(), now);
+            assert_eq!(cache.get("192.0.2.1", "whois.arin.net", now), None);
+        }
+        assert_eq!(cache.size(), 0);
+
+        assert!(is_cacheable(b"%ERROR:101: no entries found\n"));
+        assert!(is_cacheable(b"inetnum: 192.0.2.0 - 192.0.2.255\n"));
+    }
+
+    #[test]
+    fn cache_negative() {
+        assert!(is_negative(b"%ERROR:101: no entries found\n"));
+        assert!(is_negative(b"No match found for n + 192.0.2.1.\n"));
+        assert!(!is_negative(b"inetnum: 192.0.2.0 - 192.0.2.255\n"));
+    }
+}
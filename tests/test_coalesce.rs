@@ -0,0 +1,135 @@
+extern crate futures;
+extern crate owhois;
+extern crate tokio_core;
+extern crate tokio_io;
+
+#[cfg(test)]
+mod test_coalesce {
+    use futures::Future;
+    use owhois::coalesce::{Coalescer, RequestFuture};
+    use std::cell::Cell;
+    use std::io::{self, BufRead, BufReader, Write};
+    use std::net::{SocketAddr, TcpListener};
+    use std::rc::Rc;
+    use std::thread;
+    use std::time::Duration;
+    use tokio_core::net::TcpStream;
+    use tokio_core::reactor::{Core, Handle};
+    use tokio_io::io::{read_to_end, write_all};
+
+    /* Run a Whois server that answers a single query with the given
+     * response, after a short delay, and return its address.  The
+     * server thread returns the query line and the listener, so that
+     * the test can check whether any other connections were made. */
+    fn upstream(response: &'static str)
+            -> (SocketAddr, thread::JoinHandle<(String, TcpListener)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            thread::sleep(Duration::from_millis(50));
+            stream.write_all(response.as_bytes()).unwrap();
+            (line.trim().to_owned(), listener)
+        });
+        (address, server)
+    }
+
+    /* Send the query to the server at the given address, and count
+     * the request. */
+    fn request(address: SocketAddr, query: &'static str, count: &Rc<Cell<usize>>,
+               handle: &Handle) -> RequestFuture<Vec<u8>> {
+        count.set(count.get() + 1);
+        Box::new(TcpStream::connect(&address, handle)
+            .and_then(move |stream| write_all(stream, format!("{}\r\n", query)))
+            .and_then(|(stream, _)| read_to_end(stream, Vec::new()))
+            .map(|(_, response)| response))
+    }
+
+    #[test]
+    fn coalesce_shared() {
+        let mut core = Core::new().unwrap();
+        let handle = core.handle();
+        let (address, server) = upstream("% response\n");
+        let coalescer = Coalescer::new();
+        let count = Rc::new(Cell::new(0));
+
+        let first = coalescer.query("192.0.2.1", "whois.example.net", || {
+            request(address, "192.0.2.1", &count, &handle)
+        });
+        let second = coalescer.query(" 192.0.2.1 ", "WHOIS.EXAMPLE.NET", || {
+            request(address, "192.0.2.1", &count, &handle)
+        });
+        assert!(coalescer.is_in_flight("192.0.2.1", "whois.example.net"));
+        assert!(!coalescer.is_in_flight("192.0.2.1", "whois.example.org"));
+
+        /* Queries to other servers are not shared. */
+        let other = coalescer.query("192.0.2.1", "whois.example.org", || {
+            count.set(count.get() + 1);
+            Box::new(futures::future::ok(b"% other\n".to_vec()))
+        });
+
+        let (first, second, other) =
+            core.run(first.join3(second, other)).unwrap();
+        assert_eq!(first, b"% response\n".to_vec());
+        assert_eq!(second, b"% response\n".to_vec());
+        assert_eq!(other, b"% other\n".to_vec());
+        assert_eq!(count.get(), 2);
+
+        /* The upstream server received one query, on one connection,
+         * and the request is no longer in progress. */
+        let (line, listener) = server.join().unwrap();
+        assert_eq!(line, "192.0.2.1");
+        listener.set_nonblocking(true).unwrap();
+        assert_eq!(listener.accept().unwrap_err().kind(),
+                   io::ErrorKind::WouldBlock);
+        assert!(!coalescer.is_in_flight("192.0.2.1", "whois.example.net"));
+    }
+
+    #[test]
+    fn coalesce_error() {
+        let mut core = Core::new().unwrap();
+        let handle = core.handle();
+        let address = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap()
+        };
+        let coalescer = Coalescer::new();
+        let count = Rc::new(Cell::new(0));
+
+        let results: Vec<Result<Vec<u8>, (io::ErrorKind, String)>> =
+            (0..3).map(|_| {
+                coalescer.query("192.0.2.1", "whois.example.net", || {
+                    request(address, "192.0.2.1", &count, &handle)
+                }).then(|result| {
+                    Ok::<_, ()>(result.map_err(|e| (e.kind(), e.to_string())))
+                })
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|future| core.run(future).unwrap())
+            .collect();
+        assert_eq!(count.get(), 1);
+
+        /* Every waiter receives the error, with the same kind and
+         * message. */
+        let error = results[0].clone().unwrap_err();
+        assert_eq!(error.0, io::ErrorKind::ConnectionRefused);
+        for result in &results {
+            assert_eq!(result, &Err(error.clone()));
+        }
+
+        /* The failed request is no longer in progress, so the next
+         * query makes a new request. */
+        assert!(!coalescer.is_in_flight("192.0.2.1", "whois.example.net"));
+        let result =
+            core.run(coalescer.query("192.0.2.1", "whois.example.net", || {
+                request(address, "192.0.2.1", &count, &handle)
+            }));
+        assert_eq!(result.unwrap_err().kind(),
+                   io::ErrorKind::ConnectionRefused);
+        assert_eq!(count.get(), 2);
+    }
+}
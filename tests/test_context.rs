@@ -1,8 +1,12 @@
+extern crate ipnet;
 extern crate owhois;
 
 #[cfg(test)]
 mod test_context {
-    use owhois::context::Context;
+    use ipnet::{Ipv4Net, Ipv6Net};
+    use owhois::context::{Context, Resource, embedded_ipv4_net, parse_domain,
+                           parse_ip_range, parse_reverse_zone};
+    use owhois::target::{UpstreamProtocol, UpstreamTarget};
     use std::str::FromStr;
 
     #[test]
@@ -12,6 +16,7 @@ mod test_context {
                 "testdata/test_lookup/ipv4_data_1",
                 "testdata/test_lookup/ipv6_data_1",
                 "testdata/test_lookup/asn_data_1",
+                "testdata/test_lookup/domain_data_1",
             );
 
         let s1: String = String::from_str("first-server").unwrap();
@@ -45,4 +50,282 @@ mod test_context {
         let value = context.lookup("AS2500-AS2600");
         assert_eq!(value, Some(&s3));
     }
+
+    #[test]
+    fn context_parts() {
+        let context =
+            Context::from_files(
+                "testdata/test_lookup/ipv4_data_1",
+                "testdata/test_lookup/ipv6_data_1",
+                "testdata/test_lookup/asn_data_1",
+                "testdata/test_lookup/domain_data_1",
+            );
+
+        let s1 = "first-server".to_owned();
+        let s2 = "second-server".to_owned();
+        let s3 = "third-server".to_owned();
+
+        assert_eq!(context.lookup_parts("AS500-AS2500"),
+                   vec![("AS500-AS1000".to_owned(),  Some(&s1)),
+                        ("AS1001-AS2000".to_owned(), Some(&s2)),
+                        ("AS2001-AS2500".to_owned(), Some(&s3))]);
+        assert_eq!(context.lookup_parts("2.0.0.0/7"),
+                   vec![("2.0.0.0/8".to_owned(), Some(&s2)),
+                        ("3.0.0.0/8".to_owned(), Some(&s3))]);
+        assert_eq!(context.lookup_parts("0000::/15"),
+                   vec![("::/16".to_owned(),  None),
+                        ("1::/16".to_owned(), Some(&s1))]);
+        assert_eq!(context.lookup_parts("AS1"),
+                   vec![("AS1".to_owned(), Some(&s1))]);
+        assert_eq!(context.lookup_parts("AS0-AS4294967295"),
+                   vec![("AS0".to_owned(),                None),
+                        ("AS1-AS1000".to_owned(),         Some(&s1)),
+                        ("AS1001-AS2000".to_owned(),      Some(&s2)),
+                        ("AS2001-AS3000".to_owned(),      Some(&s3)),
+                        ("AS3001-AS4294967295".to_owned(), None)]);
+        assert_eq!(context.lookup("AS0-AS4294967295"), None);
+        assert_eq!(context.lookup("AS4294967295"), None);
+
+        /* Reversed ASN ranges are not resources. */
+        assert_eq!(context.lookup("AS2500-AS500"), None);
+        assert!(context.lookup_parts("AS2500-AS500").is_empty());
+        assert!(context.lookup_parts("asdf").is_empty());
+    }
+
+    #[test]
+    fn context_targets() {
+        let context =
+            Context::from_files(
+                "testdata/test_lookup/ipv4_data_2",
+                "testdata/test_lookup/ipv6_data_2",
+                "testdata/test_lookup/asn_data_2",
+                /* There is no domain file for this data. */
+                "testdata/test_lookup/domain_data_2",
+            );
+
+        /* Servers are returned in their canonical form, and servers
+         * that are written differently are treated as the same
+         * server. */
+        assert_eq!(context.lookup("1.0.0.0").unwrap(),
+                   "whois.example.net:4343");
+        assert_eq!(context.lookup("2.0.0.0").unwrap(), "whois.example.org");
+        assert_eq!(context.lookup("AS1500").unwrap(), "whois.example.org");
+        assert_eq!(context.lookup("3.0.0.0").unwrap(),
+                   "rwhois://rwhois.example.net");
+        assert_eq!(context.lookup("0001::").unwrap(), "[2001:db8::1]:4343");
+        assert_eq!(context.servers.len(), 6);
+
+        assert_eq!(context.lookup_target("3.0.0.0"),
+                   Some(&UpstreamTarget {
+                       protocol: UpstreamProtocol::Rwhois,
+                       host:     "rwhois.example.net".to_owned(),
+                       port:     4321,
+                       tls:      false,
+                   }));
+        assert_eq!(context.lookup_target("0002::"),
+                   Some(&UpstreamTarget {
+                       protocol: UpstreamProtocol::Whois,
+                       host:     "2001:db8::2".to_owned(),
+                       port:     43,
+                       tls:      false,
+                   }));
+        assert_eq!(context.lookup_target("AS500"),
+                   Some(&UpstreamTarget {
+                       protocol: UpstreamProtocol::Whois,
+                       host:     "192.0.2.1".to_owned(),
+                       port:     4343,
+                       tls:      false,
+                   }));
+        assert_eq!(context.lookup_target("4.0.0.0"), None);
+    }
+
+    #[test]
+    fn context_ip_ranges() {
+        assert_eq!(parse_ip_range("192.0.2.0 - 192.0.2.255"),
+                   Some(vec![Resource::Ipv4(
+                       Ipv4Net::from_str("192.0.2.0/24").unwrap()
+                   )]));
+        assert_eq!(parse_ip_range("192.0.2.1-192.0.2.6"),
+                   Some(vec![
+                       Resource::Ipv4(Ipv4Net::from_str("192.0.2.1/32").unwrap()),
+                       Resource::Ipv4(Ipv4Net::from_str("192.0.2.2/31").unwrap()),
+                       Resource::Ipv4(Ipv4Net::from_str("192.0.2.4/31").unwrap()),
+                       Resource::Ipv4(Ipv4Net::from_str("192.0.2.6/32").unwrap()),
+                   ]));
+        assert_eq!(parse_ip_range("2001:db8:: - 2001:db8:ffff:ffff:ffff:ffff:ffff:ffff"),
+                   Some(vec![Resource::Ipv6(
+                       Ipv6Net::from_str("2001:db8::/32").unwrap()
+                   )]));
+        assert_eq!(parse_ip_range("0.0.0.0 - 255.255.255.255"),
+                   Some(vec![Resource::Ipv4(
+                       Ipv4Net::from_str("0.0.0.0/0").unwrap()
+                   )]));
+        assert_eq!(parse_ip_range("192.0.2.255 - 192.0.2.0"), None);
+        assert_eq!(parse_ip_range("192.0.2.0 - 2001:db8::"), None);
+        assert_eq!(parse_ip_range("192.0.2.0/24"), None);
+        assert_eq!(parse_ip_range("AS1-AS2"), None);
+
+        let context =
+            Context::from_files(
+                "testdata/test_lookup/ipv4_data_1",
+                "testdata/test_lookup/ipv6_data_1",
+                "testdata/test_lookup/asn_data_1",
+                "testdata/test_lookup/domain_data_1",
+            );
+
+        let s1 = "first-server".to_owned();
+        let s2 = "second-server".to_owned();
+
+        assert_eq!(context.lookup("1.0.0.0 - 1.255.255.255"), Some(&s1));
+        assert_eq!(context.lookup("1.0.0.1 - 1.0.0.6"), Some(&s1));
+        assert_eq!(context.lookup("0002:: - 0002::ffff"), Some(&s2));
+        assert_eq!(context.lookup("1.128.0.0 - 2.127.255.255"), None);
+        assert_eq!(context.lookup("0.0.0.0 - 1.255.255.255"), None);
+
+        assert_eq!(context.lookup_parts("1.128.0.0 - 2.127.255.255"),
+                   vec![("1.128.0.0/9".to_owned(), Some(&s1)),
+                        ("2.0.0.0/9".to_owned(),   Some(&s2))]);
+        assert_eq!(context.lookup_parts("0.255.255.255 - 1.0.0.0"),
+                   vec![("0.255.255.255/32".to_owned(), None),
+                        ("1.0.0.0/32".to_owned(),       Some(&s1))]);
+    }
+
+    #[test]
+    fn context_reverse_zones() {
+        let ipv4 = |s: &str| Some(Resource::Ipv4(Ipv4Net::from_str(s).unwrap()));
+        let ipv6 = |s: &str| Some(Resource::Ipv6(Ipv6Net::from_str(s).unwrap()));
+
+        assert_eq!(parse_reverse_zone("2.0.192.in-addr.arpa"),
+                   ipv4("192.0.2.0/24"));
+        assert_eq!(parse_reverse_zone("192.IN-ADDR.ARPA."),
+                   ipv4("192.0.0.0/8"));
+        assert_eq!(parse_reverse_zone("1.2.0.192.in-addr.arpa"),
+                   ipv4("192.0.2.1/32"));
+        assert_eq!(parse_reverse_zone("64/26.2.0.192.in-addr.arpa"),
+                   ipv4("192.0.2.64/26"));
+        assert_eq!(parse_reverse_zone("128-25.2.0.192.in-addr.arpa"),
+                   ipv4("192.0.2.128/25"));
+        assert_eq!(parse_reverse_zone("8.b.d.0.1.0.0.2.ip6.arpa"),
+                   ipv6("2001:db8::/32"));
+        assert_eq!(parse_reverse_zone("2.ip6.arpa"), ipv6("2000::/4"));
+
+        /* Misaligned or invalid classless labels, and invalid zones. */
+        assert_eq!(parse_reverse_zone("65/26.2.0.192.in-addr.arpa"), None);
+        assert_eq!(parse_reverse_zone("0/24.2.0.192.in-addr.arpa"), None);
+        assert_eq!(parse_reverse_zone("0/25.0.192.in-addr.arpa"), None);
+        assert_eq!(parse_reverse_zone("256.0.192.in-addr.arpa"), None);
+        assert_eq!(parse_reverse_zone("1.1.2.0.192.in-addr.arpa"), None);
+        assert_eq!(parse_reverse_zone("in-addr.arpa"), None);
+        assert_eq!(parse_reverse_zone("db8.ip6.arpa"), None);
+        assert_eq!(parse_reverse_zone("example.com"), None);
+
+        let context =
+            Context::from_files(
+                "testdata/test_lookup/ipv4_data_1",
+                "testdata/test_lookup/ipv6_data_1",
+                "testdata/test_lookup/asn_data_1",
+                "testdata/test_lookup/domain_data_1",
+            );
+
+        let s1 = "first-server".to_owned();
+        let s2 = "second-server".to_owned();
+
+        assert_eq!(context.lookup("2.0.0.1.in-addr.arpa"), Some(&s1));
+        assert_eq!(context.lookup("0/25.0.0.2.in-addr.arpa"), Some(&s2));
+        assert_eq!(context.lookup("2.0.0.0.ip6.arpa"), Some(&s2));
+        assert_eq!(context.lookup("4.in-addr.arpa"), None);
+    }
+
+    #[test]
+    fn context_embedded_ipv4() {
+        let embedded = |s: &str| {
+            embedded_ipv4_net(Ipv6Net::from_str(s).unwrap())
+                .map(|net| net.to_string())
+        };
+        let ipv4 = |s: &str| Some(s.to_owned());
+
+        assert_eq!(embedded("::ffff:192.0.2.1/128"), ipv4("192.0.2.1/32"));
+        assert_eq!(embedded("::ffff:192.0.2.0/120"), ipv4("192.0.2.0/24"));
+        assert_eq!(embedded("64:ff9b::c000:201/128"), ipv4("192.0.2.1/32"));
+        assert_eq!(embedded("2002:c000:201::1/128"), ipv4("192.0.2.1/32"));
+        assert_eq!(embedded("2002:c000:200::/40"), ipv4("192.0.2.0/24"));
+        assert_eq!(embedded("2002:c000:201::/48"), ipv4("192.0.2.1/32"));
+        /* The Teredo client address is 192.0.2.1, with each bit
+         * inverted. */
+        assert_eq!(embedded("2001:0:4136:e378:8000:63bf:3fff:fdfe/128"),
+                   ipv4("192.0.2.1/32"));
+
+        /* Prefixes that do not include the bits that precede the
+         * IPv4 address, and other addresses. */
+        assert_eq!(embedded("2002::/16"), None);
+        assert_eq!(embedded("::ffff:0:0/96"), None);
+        assert_eq!(embedded("::ffff:0:0/95"), None);
+        assert_eq!(embedded("2001:0:4136:e378::/64"), None);
+        assert_eq!(embedded("2001:db8::1/128"), None);
+        assert_eq!(embedded("::1/128"), None);
+
+        let mut context =
+            Context::from_files(
+                "testdata/test_lookup/ipv4_data_1",
+                "testdata/test_lookup/ipv6_data_1",
+                "testdata/test_lookup/asn_data_1",
+                "testdata/test_lookup/domain_data_1",
+            );
+
+        let s1 = "first-server".to_owned();
+        let s2 = "second-server".to_owned();
+
+        /* Embedded addresses are only used when enabled. */
+        assert_eq!(context.lookup("2002:102:304::1"), None);
+        assert_eq!(context.embedded_ipv4("2002:102:304::1"), None);
+
+        context.embedded_ipv4 = true;
+        assert_eq!(context.lookup("2002:102:304::1"), Some(&s1));
+        assert_eq!(context.lookup("::ffff:1.2.3.4"), Some(&s1));
+        assert_eq!(context.lookup("::ffff:4.2.3.4"), None);
+        assert_eq!(context.lookup("0002::"), Some(&s2));
+        assert_eq!(context.embedded_ipv4("64:ff9b::1.2.3.4"),
+                   Some(Ipv4Net::from_str("1.2.3.4/32").unwrap()));
+        assert_eq!(context.embedded_ipv4("1.2.3.4"), None);
+    }
+
+    #[test]
+    fn context_domains() {
+        let domain = |s: &str| Some(s.to_owned());
+        assert_eq!(parse_domain("Example.COM."), domain("example.com"));
+        assert_eq!(parse_domain("bücher.example"),
+                   domain("xn--bcher-kva.example"));
+        assert_eq!(parse_domain("пример.рф"), domain("xn--e1afmkfd.xn--p1ai"));
+        assert_eq!(parse_domain("com"), None);
+        assert_eq!(parse_domain("EXAMPLE-ARIN"), None);
+        assert_eq!(parse_domain("192.0.2"), None);
+        assert_eq!(parse_domain("-example.com"), None);
+        assert_eq!(parse_domain("example..com"), None);
+        assert_eq!(parse_domain("user@example.com"), None);
+        assert_eq!(parse_domain("example com"), None);
+
+        let context =
+            Context::from_files(
+                "testdata/test_lookup/ipv4_data_1",
+                "testdata/test_lookup/ipv6_data_1",
+                "testdata/test_lookup/asn_data_1",
+                "testdata/test_lookup/domain_data_1",
+            );
+
+        let s1 = "first-server".to_owned();
+        let s2 = "second-server".to_owned();
+        let s3 = "third-server".to_owned();
+
+        /* The longest matching suffix is used. */
+        assert_eq!(context.lookup("example.com"), Some(&s1));
+        assert_eq!(context.lookup("www.EXAMPLE.com"), Some(&s1));
+        assert_eq!(context.lookup("example.uk"), Some(&s2));
+        assert_eq!(context.lookup("example.co.uk"), Some(&s3));
+        assert_eq!(context.lookup("пример.рф"), Some(&s2));
+        assert_eq!(context.lookup("example.net"), None);
+        assert!(context.lookup_parts("example.com").is_empty());
+
+        /* Reverse zones are still routed by prefix. */
+        assert_eq!(context.lookup("2.0.0.1.in-addr.arpa"), Some(&s1));
+    }
 }
@@ -0,0 +1,22 @@
+extern crate owhois;
+
+#[cfg(test)]
+mod test_data_root_zone {
+    use owhois::data::root_zone::{parse_root_zone_data, parse_root_zone_whois};
+
+    #[test]
+    fn root_zone() {
+        /* TLDs without a Whois server are skipped. */
+        let entries = parse_root_zone_data("testdata/test_data_root_zone");
+        assert_eq!(entries,
+                   vec![("com".to_owned(), "whois.verisign-grs.com".to_owned()),
+                        ("xn--p1ai".to_owned(), "whois.tcinet.ru".to_owned())]);
+
+        assert_eq!(parse_root_zone_whois("domain: РФ\nwhois: WHOIS.TCINET.RU\n"),
+                   Some(("xn--p1ai".to_owned(), "whois.tcinet.ru".to_owned())));
+        assert_eq!(parse_root_zone_whois("% No entries found\n"), None);
+
+        /* The root zone data is optional. */
+        assert!(parse_root_zone_data("testdata/test_data_iana").is_empty());
+    }
+}
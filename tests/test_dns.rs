@@ -0,0 +1,94 @@
+extern crate owhois;
+
+#[cfg(test)]
+mod test_dns {
+    use owhois::dns::{Answer, TYPE_A, TYPE_AAAA, build_query,
+                      nameservers_from_file, parse_response};
+    use std::net::{IpAddr, SocketAddr};
+    use std::str::FromStr;
+
+    /* Return a response to a query for www.example.net, with the given
+     * flags and answer records.  Each record is (type, TTL, data), and
+     * has a compressed pointer to the question name. */
+    fn response(id: u16, flags: u16, records: &[(u16, u32, Vec<u8>)]) -> Vec<u8> {
+        let mut query = build_query(id, "www.example.net", TYPE_A).unwrap();
+        query[2] = (flags >> 8) as u8;
+        query[3] = flags as u8;
+        query[7] = records.len() as u8;
+        for &(record_type, ttl, ref data) in records {
+            query.extend_from_slice(&[0xC0, 12]);
+            query.extend_from_slice(&[(record_type >> 8) as u8,
+                                      record_type as u8, 0, 1]);
+            query.extend_from_slice(&[(ttl >> 24) as u8, (ttl >> 16) as u8,
+                                      (ttl >> 8) as u8, ttl as u8]);
+            query.extend_from_slice(&[0, data.len() as u8]);
+            query.extend_from_slice(data);
+        }
+        query
+    }
+
+    fn address(value: &str) -> IpAddr {
+        IpAddr::from_str(value).unwrap()
+    }
+
+    #[test]
+    fn dns_nameservers() {
+        assert_eq!(nameservers_from_file("testdata/test_dns/resolv_conf_1"),
+                   vec![SocketAddr::from_str("192.0.2.53:53").unwrap(),
+                        SocketAddr::from_str("[2001:db8::53]:53").unwrap()]);
+        assert_eq!(nameservers_from_file("testdata/test_dns/missing"),
+                   Vec::<SocketAddr>::new());
+    }
+
+    #[test]
+    fn dns_build_query() {
+        assert_eq!(build_query(0x1234, "whois.example.net.", TYPE_AAAA).unwrap(),
+                   b"\x12\x34\x01\x00\x00\x01\x00\x00\x00\x00\x00\x00\
+                     \x05whois\x07example\x03net\x00\x00\x1c\x00\x01".to_vec());
+        assert!(build_query(1, "", TYPE_A).is_err());
+        assert!(build_query(1, "whois..example.net", TYPE_A).is_err());
+        assert!(build_query(1, &"a".repeat(64), TYPE_A).is_err());
+    }
+
+    #[test]
+    fn dns_parse_response() {
+        /* The TTL is the lowest of the alias and address records. */
+        let packet = response(7, 0x8180, &[
+            (5, 300, b"\x03web\xC0\x10".to_vec()),
+            (1, 120, vec![192, 0, 2, 1]),
+            (1, 3600, vec![192, 0, 2, 2]),
+        ]);
+        assert_eq!(parse_response(7, TYPE_A, &packet).unwrap(),
+                   Answer { addresses: vec![address("192.0.2.1"),
+                                            address("192.0.2.2")],
+                            ttl: Some(120) });
+
+        /* Records of other types are ignored. */
+        assert_eq!(parse_response(7, TYPE_AAAA, &packet).unwrap(),
+                   Answer::default());
+        let mut ipv6 = vec![0x20, 0x01, 0x0d, 0xb8];
+        ipv6.extend_from_slice(&[0; 11]);
+        ipv6.push(1);
+        let packet = response(7, 0x8180, &[(28, 60, ipv6)]);
+        assert_eq!(parse_response(7, TYPE_AAAA, &packet).unwrap(),
+                   Answer { addresses: vec![address("2001:db8::1")],
+                            ttl: Some(60) });
+
+        /* A name that does not exist has no addresses. */
+        let packet = response(7, 0x8183, &[]);
+        assert_eq!(parse_response(7, TYPE_A, &packet).unwrap(),
+                   Answer::default());
+
+        /* Mismatched, truncated, failed and malformed responses are
+         * errors. */
+        let packet = response(7, 0x8180, &[(1, 120, vec![192, 0, 2, 1])]);
+        assert!(parse_response(8, TYPE_A, &packet).is_err());
+        assert!(parse_response(7, TYPE_A, &packet[..packet.len() - 1]).is_err());
+        assert!(parse_response(7, TYPE_A,
+                               &response(7, 0x8380, &[])).is_err());
+        assert!(parse_response(7, TYPE_A,
+                               &response(7, 0x8182, &[])).is_err());
+        assert!(parse_response(7, TYPE_A,
+                               &response(7, 0x0100, &[])).is_err());
+    }
+}
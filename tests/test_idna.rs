@@ -0,0 +1,32 @@
+extern crate owhois;
+
+#[cfg(test)]
+mod test_idna {
+    use owhois::idna::{domain_to_ascii, punycode_encode};
+
+    #[test]
+    fn idna_punycode() {
+        assert_eq!(punycode_encode("bücher").unwrap(), "bcher-kva");
+        assert_eq!(punycode_encode("münchen").unwrap(), "mnchen-3ya");
+        assert_eq!(punycode_encode("рф").unwrap(), "p1ai");
+        /* Samples from RFC 3492, section 7.1. */
+        assert_eq!(punycode_encode("他们为什么不说中文").unwrap(),
+                   "ihqwcrb4cv8a8dqg056pqjye");
+        assert_eq!(punycode_encode("3年b組金八先生").unwrap(),
+                   "3b-ww4c5e180e575a65lsy2b");
+        assert_eq!(punycode_encode("example").unwrap(), "example-");
+    }
+
+    #[test]
+    fn idna_domain_to_ascii() {
+        assert_eq!(domain_to_ascii("Example.COM.").unwrap(), "example.com");
+        assert_eq!(domain_to_ascii("BÜCHER.example").unwrap(),
+                   "xn--bcher-kva.example");
+        assert_eq!(domain_to_ascii("例え。テスト").unwrap(),
+                   "xn--r8jz45g.xn--zckzah");
+        assert_eq!(domain_to_ascii("example..com"), None);
+        assert_eq!(domain_to_ascii(""), None);
+        assert_eq!(domain_to_ascii(&"a".repeat(64)), None);
+        assert_eq!(domain_to_ascii(&["a"; 128].join(".")), None);
+    }
+}
@@ -0,0 +1,91 @@
+extern crate futures;
+extern crate owhois;
+
+#[cfg(test)]
+mod test_input {
+    use futures::{Future, Stream};
+    use owhois::input::{FirstLine, Input, first_line, query_lines};
+    use std::io::Cursor;
+
+    fn read_lines(data: &[u8], max_length: usize) -> Vec<Input> {
+        query_lines(Cursor::new(data.to_vec()), max_length)
+            .collect()
+            .wait()
+            .unwrap()
+    }
+
+    fn query(line: &str) -> Input {
+        Input::Query(line.to_owned())
+    }
+
+    #[test]
+    fn input_lines() {
+        assert_eq!(read_lines(b"1.0.0.0/8\r\n", 1024),
+                   vec![query("1.0.0.0/8")]);
+        assert_eq!(read_lines(b"1.0.0.0/8\n", 1024),
+                   vec![query("1.0.0.0/8")]);
+        assert_eq!(read_lines(b"1.0.0.0/8", 1024),
+                   vec![query("1.0.0.0/8")]);
+        assert_eq!(read_lines(b"-r\t1.0.0.0/8\r\nAS1\r\n", 1024),
+                   vec![query("-r\t1.0.0.0/8"), query("AS1")]);
+    }
+
+    #[test]
+    fn input_eof() {
+        assert_eq!(read_lines(b"", 1024), vec![]);
+        assert_eq!(read_lines(b"\r\n", 1024), vec![query("")]);
+    }
+
+    #[test]
+    fn input_first_line() {
+        let first = |data: &[u8]| {
+            first_line(read_lines(data, 16).into_iter().next())
+        };
+        assert_eq!(first(b"192.0.2.1\r\n"),
+                   FirstLine::Query("192.0.2.1".to_owned()));
+        assert_eq!(first(b" AS1 \r\n192.0.2.1\r\n"),
+                   FirstLine::Query(" AS1 ".to_owned()));
+
+        /* The connection is closed without a reply on EOF, and empty
+         * queries are refused. */
+        assert_eq!(first(b""), FirstLine::Closed);
+        for data in &[&b"\r\n"[..], b"\n", b" \t \r\n", b" "] {
+            assert_eq!(first(data),
+                       FirstLine::Refused { reason:  "empty query",
+                                            message: "% Empty query\n" });
+        }
+
+        assert_eq!(first(b"192.0.2.1 192.0.2.2\r\n"),
+                   FirstLine::Refused { reason:  "query too long",
+                                        message: "% Query too long\n" });
+        assert_eq!(first(b"192.0.2.1\x00\r\n"),
+                   FirstLine::Refused {
+                       reason:  "invalid characters",
+                       message: "% Query contains invalid characters\n"
+                   });
+    }
+
+    #[test]
+    fn input_too_long() {
+        assert_eq!(read_lines(b"AS1234\r\n", 6), vec![query("AS1234")]);
+        assert_eq!(read_lines(b"AS12345\r\n", 6), vec![Input::TooLong]);
+        assert_eq!(read_lines(b"AS12345", 6), vec![Input::TooLong]);
+
+        /* The remainder of an overlong line is discarded. */
+        let mut data = vec![b'1'; 5000];
+        data.extend_from_slice(b"\r\nAS1\r\n");
+        assert_eq!(read_lines(&data, 1024),
+                   vec![Input::TooLong, query("AS1")]);
+    }
+
+    #[test]
+    fn input_invalid() {
+        assert_eq!(read_lines(b"AS1\rAS2\r\n", 1024), vec![Input::Invalid]);
+        assert_eq!(read_lines(b"AS1\x00\r\n", 1024), vec![Input::Invalid]);
+        assert_eq!(read_lines(b"AS1\x1b[0m\r\n", 1024), vec![Input::Invalid]);
+        assert_eq!(read_lines(b"AS1\x7f\r\n", 1024), vec![Input::Invalid]);
+        assert_eq!(read_lines(b"\xff\xfe\r\n", 1024), vec![Input::Invalid]);
+        assert_eq!(read_lines(b"AS1\x00\r\nAS2\r\n", 1024),
+                   vec![Input::Invalid, query("AS2")]);
+    }
+}
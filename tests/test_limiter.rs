@@ -0,0 +1,59 @@
+extern crate futures;
+extern crate owhois;
+
+#[cfg(test)]
+mod test_limiter {
+    use futures::Future;
+    use owhois::limiter::Limiter;
+
+    #[test]
+    fn limiter_unlimited() {
+        let limiter = Limiter::new();
+        let permit1 = limiter.acquire("whois.example.net", 0).wait().unwrap();
+        let permit2 = limiter.acquire("whois.example.net", 0).wait().unwrap();
+        assert_eq!(limiter.stats("whois.example.net").active, 2);
+        drop(permit1);
+        drop(permit2);
+        assert_eq!(limiter.stats("whois.example.net").active, 0);
+    }
+
+    #[test]
+    fn limiter_queue() {
+        let limiter = Limiter::new();
+        let permit1 = limiter.acquire("whois.example.net", 1).wait().unwrap();
+        let acquire2 = limiter.acquire("whois.example.net", 1);
+        let acquire3 = limiter.acquire("WHOIS.EXAMPLE.NET", 1);
+
+        let stats = limiter.stats("whois.example.net");
+        assert_eq!(stats.active, 1);
+        assert_eq!(stats.queue_depth, 2);
+        assert_eq!(stats.max_queue_depth, 2);
+
+        /* Other servers are not affected. */
+        let other = limiter.acquire("whois.example.com", 1).wait().unwrap();
+        drop(other);
+
+        drop(permit1);
+        let permit2 = acquire2.wait().unwrap();
+        let stats = limiter.stats("whois.example.net");
+        assert_eq!(stats.active, 1);
+        assert_eq!(stats.queue_depth, 1);
+        assert_eq!(stats.waited, 1);
+
+        /* An abandoned request is removed from the queue. */
+        drop(acquire3);
+        assert_eq!(limiter.stats("whois.example.net").queue_depth, 0);
+
+        drop(permit2);
+        let stats = limiter.stats("whois.example.net");
+        assert_eq!(stats.active, 0);
+        assert_eq!(stats.queue_depth, 0);
+
+        let all_stats = limiter.all_stats();
+        assert_eq!(all_stats.len(), 2);
+        assert_eq!(all_stats[0].0, "whois.example.com");
+        assert_eq!(all_stats[0].1.waited, 0);
+        assert_eq!(all_stats[1].0, "whois.example.net");
+        assert_eq!(all_stats[1].1, stats);
+    }
+}
@@ -0,0 +1,78 @@
+extern crate futures;
+extern crate owhois;
+extern crate tokio_core;
+
+#[cfg(test)]
+mod test_listener {
+    use futures::Stream;
+    use owhois::listener::{bind, ListenAddress};
+    use std::env;
+    use std::net::{SocketAddr, TcpStream};
+    use std::os::unix::net::UnixStream;
+    use std::path::PathBuf;
+    use std::process;
+    use std::str::FromStr;
+    use tokio_core::reactor::Core;
+
+    #[test]
+    fn listener_parse() {
+        assert_eq!(ListenAddress::from_str("0.0.0.0:43"),
+                   Ok(ListenAddress::Tcp(SocketAddr::from_str("0.0.0.0:43")
+                                             .unwrap())));
+        assert_eq!(ListenAddress::from_str("[::]:4343"),
+                   Ok(ListenAddress::Tcp(SocketAddr::from_str("[::]:4343")
+                                             .unwrap())));
+        assert_eq!(ListenAddress::from_str("unix:/run/owhois.sock"),
+                   Ok(ListenAddress::Unix(PathBuf::from("/run/owhois.sock"))));
+        assert!(ListenAddress::from_str("unix:").is_err());
+        assert!(ListenAddress::from_str("0.0.0.0").is_err());
+        assert!(ListenAddress::from_str("localhost:43").is_err());
+        assert_eq!(ListenAddress::from_str("[::1]:43").unwrap().to_string(),
+                   "[::1]:43");
+        assert_eq!(ListenAddress::from_str("unix:/tmp/a").unwrap().to_string(),
+                   "unix:/tmp/a");
+    }
+
+    #[test]
+    fn listener_bind() {
+        let mut socket_path = env::temp_dir();
+        socket_path.push(format!("owhois-test-{}.sock", process::id()));
+        let addresses = vec![
+            ListenAddress::from_str("127.0.0.1:0").unwrap(),
+            ListenAddress::Unix(socket_path.clone()),
+        ];
+
+        let mut core = Core::new().unwrap();
+        let handle = core.handle();
+        let mut listeners = bind(&addresses, &handle).unwrap();
+        let unix_listener = listeners.pop().unwrap();
+        let tcp_listener = listeners.pop().unwrap();
+        assert_eq!(unix_listener.address, ListenAddress::Unix(socket_path.clone()));
+
+        let tcp_address = match tcp_listener.address {
+            ListenAddress::Tcp(address) => address,
+            _ => panic!("expected a TCP address")
+        };
+        assert_ne!(tcp_address.port(), 0);
+        let tcp_client = TcpStream::connect(tcp_address).unwrap();
+        let (connection, _) =
+            core.run(tcp_listener.incoming.into_future())
+                .map_err(|e| e.0)
+                .unwrap();
+        let (_, client_addr) = connection.unwrap();
+        assert_eq!(client_addr, tcp_client.local_addr().unwrap());
+
+        let _unix_client = UnixStream::connect(&socket_path).unwrap();
+        let (connection, _) =
+            core.run(unix_listener.incoming.into_future())
+                .map_err(|e| e.0)
+                .unwrap();
+        let (_, client_addr) = connection.unwrap();
+        assert_eq!(client_addr, SocketAddr::from_str("127.0.0.1:0").unwrap());
+
+        /* A stale socket is replaced when binding again. */
+        drop(bind(&[ListenAddress::Unix(socket_path.clone())], &handle)
+                 .unwrap());
+        std::fs::remove_file(&socket_path).unwrap();
+    }
+}
@@ -77,4 +77,80 @@ mod test_lookup {
         );
         assert_eq!(value, Some(1));
     }
+
+    #[test]
+    fn asn_lookup_parts() {
+        let asn_lookup: AsnResourceLookup =
+            ResourceLookup::from_iter(vec![
+                (AsnRange { start: Asn { value: 1 },
+                            end:   Asn { value: 10 } }, 1),
+                (AsnRange { start: Asn { value: 4 },
+                            end:   Asn { value: 6 } }, 2),
+                (AsnRange { start: Asn { value: 20 },
+                            end:   Asn { value: 30 } }, 3),
+            ]);
+        let parts = asn_lookup.get_parts(
+            AsnRange { start: Asn { value: 2 },
+                       end:   Asn { value: 25 } }
+        ).unwrap();
+        assert_eq!(parts, vec![
+            (AsnRange { start: Asn { value: 2 },
+                        end:   Asn { value: 4 } }, Some(1)),
+            (AsnRange { start: Asn { value: 4 },
+                        end:   Asn { value: 6 } }, Some(2)),
+            (AsnRange { start: Asn { value: 6 },
+                        end:   Asn { value: 10 } }, Some(1)),
+            (AsnRange { start: Asn { value: 10 },
+                        end:   Asn { value: 20 } }, None),
+            (AsnRange { start: Asn { value: 20 },
+                        end:   Asn { value: 25 } }, Some(3)),
+        ]);
+
+        /* Ranges that overlap too many entries are not split. */
+        let asn_lookup: AsnResourceLookup =
+            ResourceLookup::from_iter((0..2000).map(|i| {
+                (AsnRange { start: Asn { value: i * 2 },
+                            end:   Asn { value: i * 2 + 1 } }, i)
+            }));
+        let parts = asn_lookup.get_parts(
+            AsnRange { start: Asn { value: 0 },
+                       end:   Asn { value: 4000 } }
+        );
+        assert_eq!(parts, None);
+    }
+
+    #[test]
+    fn asn_lookup_range_bounds() {
+        let asn_lookup: AsnResourceLookup =
+            ResourceLookup::from_iter(vec![
+                (AsnRange { start: Asn { value: 1 },
+                            end:   Asn { value: 10 } }, 1),
+                (AsnRange { start: Asn { value: 10 },
+                            end:   Asn { value: 0 } }, 2),
+            ]);
+
+        /* A range that includes the last ASN has an end of zero. */
+        let value = asn_lookup.get_longest_match_value(
+            AsnRange { start: Asn { value: 0 },
+                       end:   Asn { value: 0 } }
+        );
+        assert_eq!(value, None);
+        let value = asn_lookup.get_longest_match_value(
+            AsnRange { start: Asn { value: 20 },
+                       end:   Asn { value: 0 } }
+        );
+        assert_eq!(value, Some(2));
+        let value = asn_lookup.get_longest_match_value(
+            AsnRange { start: Asn { value: 4294967295 },
+                       end:   Asn { value: 0 } }
+        );
+        assert_eq!(value, Some(2));
+
+        /* A reversed range matches nothing. */
+        let value = asn_lookup.get_longest_match_value(
+            AsnRange { start: Asn { value: 5 },
+                       end:   Asn { value: 3 } }
+        );
+        assert_eq!(value, None);
+    }
 }
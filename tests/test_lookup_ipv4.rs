@@ -123,4 +123,108 @@ mod test_lookup {
         );
         assert_eq!(value, None);
     }
+
+    #[test]
+    fn ipv4_lookup_default_route() {
+        let ipv4_lookup: Ipv4ResourceLookup =
+            ResourceLookup::from_iter(vec![
+                (Ipv4Net::from_str("0.0.0.0/0").unwrap(), 1),
+                (Ipv4Net::from_str("192.0.2.0/24").unwrap(), 2),
+            ]);
+        let value = ipv4_lookup.get_longest_match_value(
+            Ipv4Net::from_str("192.0.2.1/32").unwrap()
+        );
+        assert_eq!(value, Some(2));
+        let value = ipv4_lookup.get_longest_match(
+            Ipv4Net::from_str("198.51.100.1/32").unwrap()
+        );
+        assert_eq!(value, Some((Some(Ipv4Net::from_str("0.0.0.0/0").unwrap()), 1)));
+    }
+
+    #[test]
+    fn ipv4_lookup_parts() {
+        let ipv4_lookup: Ipv4ResourceLookup =
+            ResourceLookup::from_iter(vec![
+                (Ipv4Net::from_str("1.0.0.0/8").unwrap(),  1),
+                (Ipv4Net::from_str("1.0.0.0/16").unwrap(), 2),
+                (Ipv4Net::from_str("3.0.0.0/8").unwrap(),  3),
+            ]);
+        let parts = ipv4_lookup.get_parts(
+            Ipv4Net::from_str("0.0.0.0/6").unwrap()
+        ).unwrap();
+        assert_eq!(parts, vec![
+            (Ipv4Net::from_str("0.0.0.0/8").unwrap(),   None),
+            (Ipv4Net::from_str("1.0.0.0/16").unwrap(),  Some(2)),
+            (Ipv4Net::from_str("1.1.0.0/16").unwrap(),  Some(1)),
+            (Ipv4Net::from_str("1.2.0.0/15").unwrap(),  Some(1)),
+            (Ipv4Net::from_str("1.4.0.0/14").unwrap(),  Some(1)),
+            (Ipv4Net::from_str("1.8.0.0/13").unwrap(),  Some(1)),
+            (Ipv4Net::from_str("1.16.0.0/12").unwrap(), Some(1)),
+            (Ipv4Net::from_str("1.32.0.0/11").unwrap(), Some(1)),
+            (Ipv4Net::from_str("1.64.0.0/10").unwrap(), Some(1)),
+            (Ipv4Net::from_str("1.128.0.0/9").unwrap(), Some(1)),
+            (Ipv4Net::from_str("2.0.0.0/8").unwrap(),   None),
+            (Ipv4Net::from_str("3.0.0.0/8").unwrap(),   Some(3)),
+        ]);
+
+        /* A prefix within a single entry is a single part. */
+        let parts = ipv4_lookup.get_parts(
+            Ipv4Net::from_str("3.1.0.0/16").unwrap()
+        ).unwrap();
+        assert_eq!(parts, vec![
+            (Ipv4Net::from_str("3.1.0.0/16").unwrap(), Some(3)),
+        ]);
+
+        let parts = ipv4_lookup.get_parts(
+            Ipv4Net::from_str("0.0.0.0/0").unwrap()
+        ).unwrap();
+        assert_eq!(parts.last(),
+                   Some(&(Ipv4Net::from_str("128.0.0.0/1").unwrap(), None)));
+    }
+
+    #[test]
+    fn ipv4_lookup_parts_end() {
+        /* Ranges that reach the end of the address space include the
+         * entries that contain the last address. */
+        let ipv4_lookup: Ipv4ResourceLookup =
+            ResourceLookup::from_iter(vec![
+                (Ipv4Net::from_str("1.0.0.0/8").unwrap(),   1),
+                (Ipv4Net::from_str("192.0.0.0/2").unwrap(), 2),
+                (Ipv4Net::from_str("255.0.0.0/8").unwrap(), 3),
+            ]);
+        let parts = ipv4_lookup.get_parts(
+            Ipv4Net::from_str("128.0.0.0/1").unwrap()
+        ).unwrap();
+        assert_eq!(parts, vec![
+            (Ipv4Net::from_str("128.0.0.0/2").unwrap(), None),
+            (Ipv4Net::from_str("192.0.0.0/3").unwrap(), Some(2)),
+            (Ipv4Net::from_str("224.0.0.0/4").unwrap(), Some(2)),
+            (Ipv4Net::from_str("240.0.0.0/5").unwrap(), Some(2)),
+            (Ipv4Net::from_str("248.0.0.0/6").unwrap(), Some(2)),
+            (Ipv4Net::from_str("252.0.0.0/7").unwrap(), Some(2)),
+            (Ipv4Net::from_str("254.0.0.0/8").unwrap(), Some(2)),
+            (Ipv4Net::from_str("255.0.0.0/8").unwrap(), Some(3)),
+        ]);
+        let parts = ipv4_lookup.get_parts(
+            Ipv4Net::from_str("255.0.0.0/9").unwrap()
+        ).unwrap();
+        assert_eq!(parts, vec![
+            (Ipv4Net::from_str("255.0.0.0/9").unwrap(), Some(3)),
+        ]);
+
+        /* Reading stops once there are too many entries, including
+         * for ranges that reach the end of the address space. */
+        let ipv4_lookup: Ipv4ResourceLookup =
+            ResourceLookup::from_iter((0..200000u32).map(|i| {
+                (Ipv4Net::new((0x80000000 + i * 256).into(), 24).unwrap(), i)
+            }));
+        for prefix in &["0.0.0.0/0", "128.0.0.0/1", "128.0.0.0/8"] {
+            assert_eq!(ipv4_lookup.get_parts(Ipv4Net::from_str(prefix).unwrap()),
+                       None);
+        }
+        let parts = ipv4_lookup.get_parts(
+            Ipv4Net::from_str("128.0.0.0/22").unwrap()
+        ).unwrap();
+        assert_eq!(parts.len(), 4);
+    }
 }
@@ -123,4 +123,28 @@ mod test_lookup {
         );
         assert_eq!(value, None);
     }
+
+    #[test]
+    fn ipv6_lookup_parts() {
+        let ipv6_lookup: Ipv6ResourceLookup =
+            ResourceLookup::from_iter(vec![
+                (Ipv6Net::from_str("2001:db8::/33").unwrap(),      1),
+                (Ipv6Net::from_str("2001:db8:8000::/33").unwrap(), 2),
+            ]);
+        let parts = ipv6_lookup.get_parts(
+            Ipv6Net::from_str("2001:db8::/32").unwrap()
+        ).unwrap();
+        assert_eq!(parts, vec![
+            (Ipv6Net::from_str("2001:db8::/33").unwrap(),      Some(1)),
+            (Ipv6Net::from_str("2001:db8:8000::/33").unwrap(), Some(2)),
+        ]);
+
+        let parts = ipv6_lookup.get_parts(
+            Ipv6Net::from_str("::/0").unwrap()
+        ).unwrap();
+        assert_eq!(parts.first(),
+                   Some(&(Ipv6Net::from_str("::/3").unwrap(), None)));
+        assert_eq!(parts.last(),
+                   Some(&(Ipv6Net::from_str("8000::/1").unwrap(), None)));
+    }
 }
@@ -0,0 +1,73 @@
+extern crate owhois;
+
+#[cfg(test)]
+mod test_policy {
+    use owhois::context::parse_resource;
+    use owhois::policy::{AccessList, QueryPolicy};
+    use std::net::IpAddr;
+    use std::str::FromStr;
+
+    fn address(value: &str) -> IpAddr {
+        IpAddr::from_str(value).unwrap()
+    }
+
+    #[test]
+    fn access_list() {
+        let acl = AccessList::default();
+        assert!(acl.is_allowed(address("192.0.2.1")));
+        assert!(acl.is_allowed(address("2001:db8::1")));
+
+        let acl = AccessList::from_file("testdata/test_policy/acl_1");
+        assert!(acl.is_allowed(address("192.0.2.1")));
+        assert!(!acl.is_allowed(address("192.0.2.129")));
+        assert!(!acl.is_allowed(address("198.51.100.1")));
+        assert!(!acl.is_allowed(address("2001:db8::1")));
+        assert!(acl.is_allowed(address("2001:db9::1")));
+    }
+
+    #[test]
+    fn query_policy() {
+        let policy = QueryPolicy::from_file("testdata/test_policy/policy_1");
+        let client = address("198.51.100.1");
+        let check = |query: &str, upstream: &str| {
+            policy.is_allowed(client, parse_resource(query), upstream, true)
+        };
+        let check_unhandled = |query: &str, upstream: &str| {
+            policy.is_allowed(client, parse_resource(query), upstream, false)
+        };
+
+        assert!(check("192.0.2.0/24", "whois.arin.net"));
+        assert!(!check("0.0.0.0/0", "whois.arin.net"));
+        assert!(!check("EXAMPLE-ARIN", "whois.iana.org"));
+        assert!(check("example.com", "whois.verisign-grs.com"));
+        assert!(check("2001:db8::/32", "whois.arin.net"));
+        assert!(!check("2000::/3", "whois.arin.net"));
+        assert!(check("AS64496", "whois.arin.net"));
+        assert!(check("AS64496-AS64511", "whois.arin.net"));
+        assert!(!check("AS1-AS65535", "whois.arin.net"));
+        assert!(!check("192.0.2.0", "WHOIS.EXAMPLE.NET"));
+
+        /* Queries that are not covered by the mapping data (and so go
+         * to the default server) are unhandled, as are queries that
+         * are not for a resource. */
+        assert!(!check_unhandled("192.0.2.0/24", "whois.iana.org"));
+        assert!(!check_unhandled("AS64496", "whois.iana.org"));
+        assert!(!check_unhandled("EXAMPLE-ARIN", "whois.iana.org"));
+
+        /* The first matching rule applies. */
+        assert!(policy.is_allowed(address("192.0.2.1"),
+                                  parse_resource("0.0.0.0/0"),
+                                  "whois.arin.net", true));
+        assert!(policy.is_allowed(address("192.0.2.1"),
+                                  parse_resource("EXAMPLE-ARIN"),
+                                  "whois.iana.org", false));
+    }
+
+    #[test]
+    fn query_policy_default() {
+        let policy = QueryPolicy::default();
+        assert!(policy.is_allowed(address("192.0.2.1"),
+                                  parse_resource("0.0.0.0/0"),
+                                  "whois.arin.net", false));
+    }
+}
@@ -0,0 +1,75 @@
+extern crate futures;
+extern crate owhois;
+
+#[cfg(test)]
+mod test_pool {
+    use futures::Future;
+    use owhois::pool::{SessionPool, is_complete_response, read_response};
+    use std::io::Cursor;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn pool_take_put() {
+        let pool = SessionPool::new();
+        let now = Instant::now();
+        assert_eq!(pool.take("whois.example.net", now), None);
+
+        pool.put("whois.example.net", 1, 2, now);
+        pool.put("whois.example.net", 2, 2, now);
+        /* The pool is full, so this connection is closed. */
+        pool.put("whois.example.net", 3, 2, now);
+        pool.put("whois.example.org", 4, 2, now);
+        assert_eq!(pool.idle_count("whois.example.net"), 2);
+
+        /* The most recently used connection is taken first. */
+        assert_eq!(pool.take("whois.example.net", now), Some(2));
+        assert_eq!(pool.take("whois.example.net", now), Some(1));
+        assert_eq!(pool.take("whois.example.net", now), None);
+        assert_eq!(pool.take("whois.example.org", now), Some(4));
+
+        let clone = pool.clone();
+        clone.put("whois.example.net", 5, 2, now);
+        assert_eq!(pool.idle_count("whois.example.net"), 1);
+    }
+
+    #[test]
+    fn pool_idle_timeout() {
+        let pool = SessionPool::new();
+        let now = Instant::now();
+        pool.put("whois.example.net", 1, 4, now);
+        pool.put("whois.example.net", 2, 4, now + Duration::from_secs(30));
+        let later = now + Duration::from_secs(75);
+        assert_eq!(pool.take("whois.example.net", later), Some(2));
+        assert_eq!(pool.take("whois.example.net", later), None);
+        assert_eq!(pool.idle_count("whois.example.net"), 0);
+    }
+
+    #[test]
+    fn pool_complete_response() {
+        assert!(is_complete_response(b"inetnum: 192.0.2.0 - 192.0.2.255\n\n\n"));
+        assert!(is_complete_response(b"% No entries found\r\n\r\n\r\n"));
+        assert!(!is_complete_response(b"inetnum: 192.0.2.0 - 192.0.2.255\n\n"));
+        assert!(!is_complete_response(b"inetnum: 192.0.2.0 - 192.0.2.255\n"));
+        assert!(!is_complete_response(b""));
+    }
+
+    #[test]
+    fn pool_read_response() {
+        let (connection, response) =
+            read_response(Cursor::new(b"rst\n\n\n".to_vec()), b"% fi".to_vec())
+                .wait().unwrap();
+        assert!(connection.is_some());
+        assert_eq!(response, b"% first\n\n\n");
+
+        /* The server closed the connection before the end of the
+         * response. */
+        let (connection, response) =
+            read_response(Cursor::new(b"rst\n".to_vec()), b"% fi".to_vec())
+                .wait().unwrap();
+        assert!(connection.is_none());
+        assert_eq!(response, b"% first\n");
+
+        assert!(read_response(Cursor::new(Vec::new()), Vec::new())
+                    .wait().is_err());
+    }
+}
@@ -0,0 +1,106 @@
+extern crate futures;
+extern crate ipnet;
+extern crate owhois;
+
+#[cfg(test)]
+mod test_proxy {
+    use futures::Future;
+    use ipnet::IpNet;
+    use owhois::proxy::{is_trusted, parse_header, read_proxy_header};
+    use std::io::{Cursor, Read};
+    use std::net::{IpAddr, SocketAddr};
+    use std::str::FromStr;
+
+    fn address(value: &str) -> Option<SocketAddr> {
+        Some(SocketAddr::from_str(value).unwrap())
+    }
+
+    fn v2_header(command: u8, family: u8, addresses: &[u8]) -> Vec<u8> {
+        let mut header = b"\r\n\r\n\x00\r\nQUIT\n".to_vec();
+        header.push(0x20 | command);
+        header.push(family);
+        header.push((addresses.len() >> 8) as u8);
+        header.push(addresses.len() as u8);
+        header.extend_from_slice(addresses);
+        header
+    }
+
+    #[test]
+    fn proxy_trusted() {
+        let trusted = vec![IpNet::from_str("10.0.0.0/8").unwrap(),
+                           IpNet::from_str("2001:db8::/32").unwrap()];
+        assert!(is_trusted(&trusted, IpAddr::from_str("10.1.2.3").unwrap()));
+        assert!(is_trusted(&trusted, IpAddr::from_str("2001:db8::1").unwrap()));
+        assert!(!is_trusted(&trusted, IpAddr::from_str("192.0.2.1").unwrap()));
+        assert!(!is_trusted(&[], IpAddr::from_str("10.1.2.3").unwrap()));
+    }
+
+    #[test]
+    fn proxy_parse_v1() {
+        assert_eq!(parse_header(b"PROXY TCP4 192.0.2.1 198.51.100.1 56324 43\r\n")
+                       .unwrap(),
+                   address("192.0.2.1:56324"));
+        assert_eq!(parse_header(b"PROXY TCP6 2001:db8::1 2001:db8::2 56324 43\r\n")
+                       .unwrap(),
+                   address("[2001:db8::1]:56324"));
+        assert_eq!(parse_header(b"PROXY UNKNOWN\r\n").unwrap(), None);
+        assert!(parse_header(b"PROXY TCP4 2001:db8::1 2001:db8::2 1 43\r\n")
+                    .is_err());
+        assert!(parse_header(b"PROXY TCP4 192.0.2.1 56324 43\r\n").is_err());
+        assert!(parse_header(b"PROXY UDP4 192.0.2.1 198.51.100.1 1 43\r\n")
+                    .is_err());
+    }
+
+    #[test]
+    fn proxy_parse_v2() {
+        let ipv4 = [192, 0, 2, 1, 198, 51, 100, 1, 0xDC, 0x04, 0, 43];
+        assert_eq!(parse_header(&v2_header(1, 0x11, &ipv4)).unwrap(),
+                   address("192.0.2.1:56324"));
+
+        let mut ipv6 = vec![0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0,
+                            0, 0, 0, 0, 0, 0, 0, 1];
+        ipv6.extend_from_slice(&[0; 16]);
+        ipv6.extend_from_slice(&[0xDC, 0x04, 0, 43]);
+        assert_eq!(parse_header(&v2_header(1, 0x21, &ipv6)).unwrap(),
+                   address("[2001:db8::1]:56324"));
+
+        /* LOCAL connections and unspecified families have no client
+         * address. */
+        assert_eq!(parse_header(&v2_header(0, 0x11, &ipv4)).unwrap(), None);
+        assert_eq!(parse_header(&v2_header(1, 0x00, &[])).unwrap(), None);
+
+        assert!(parse_header(&v2_header(1, 0x11, &ipv4[..8])).is_err());
+        assert!(parse_header(&v2_header(2, 0x11, &ipv4)).is_err());
+    }
+
+    #[test]
+    fn proxy_read_header() {
+        let data = b"PROXY TCP4 192.0.2.1 198.51.100.1 56324 43\r\nAS64496\r\n";
+        let (mut stream, client) =
+            read_proxy_header(Cursor::new(data.to_vec())).wait().unwrap();
+        assert_eq!(client, address("192.0.2.1:56324"));
+        let mut rest = String::new();
+        stream.read_to_string(&mut rest).unwrap();
+        assert_eq!(rest, "AS64496\r\n");
+
+        let mut data = v2_header(1, 0x11, &[192, 0, 2, 1, 198, 51, 100, 1,
+                                            0xDC, 0x04, 0, 43]);
+        data.extend_from_slice(b"AS64496\r\n");
+        let (mut stream, client) =
+            read_proxy_header(Cursor::new(data)).wait().unwrap();
+        assert_eq!(client, address("192.0.2.1:56324"));
+        let mut rest = String::new();
+        stream.read_to_string(&mut rest).unwrap();
+        assert_eq!(rest, "AS64496\r\n");
+
+        assert!(read_proxy_header(Cursor::new(b"AS64496\r\n".to_vec()))
+                    .wait().is_err());
+        assert!(read_proxy_header(Cursor::new(b"PROXY TCP4".to_vec()))
+                    .wait().is_err());
+        assert!(read_proxy_header(Cursor::new(vec![b'P'; 200]))
+                    .wait().is_err());
+        let long_line = format!("PROXY {}\r\n", "A".repeat(200));
+        assert!(read_proxy_header(Cursor::new(long_line.into_bytes()))
+                    .wait().is_err());
+    }
+}
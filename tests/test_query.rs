@@ -0,0 +1,79 @@
+extern crate owhois;
+
+#[cfg(test)]
+mod test_query {
+    use owhois::query::{Query, QueryFlag, QueryStyle, query_style};
+
+    #[test]
+    fn query_plain() {
+        let query = Query::parse("192.0.2.1");
+        assert_eq!(query.resource, "192.0.2.1");
+        assert!(!query.has_options());
+        assert_eq!(query.translate(QueryStyle::Ripe), "192.0.2.1");
+        assert_eq!(query.translate(QueryStyle::Arin), "192.0.2.1");
+    }
+
+    #[test]
+    fn query_ripe_flags() {
+        let query = Query::parse("-B 192.0.2.1");
+        assert_eq!(query.resource, "192.0.2.1");
+        assert_eq!(query.flags,
+                   vec![QueryFlag { name: "-B".to_owned(), value: None }]);
+
+        let query = Query::parse("-r -T inetnum 10.0.0.0/8");
+        assert_eq!(query.resource, "10.0.0.0/8");
+        assert_eq!(query.flags,
+                   vec![QueryFlag { name: "-r".to_owned(), value: None },
+                        QueryFlag { name: "-T".to_owned(),
+                                    value: Some("inetnum".to_owned()) }]);
+        assert_eq!(query.translate(QueryStyle::Ripe),
+                   "-r -T inetnum 10.0.0.0/8");
+        assert_eq!(query.translate(QueryStyle::Arin), "10.0.0.0/8");
+        assert_eq!(query.translate(QueryStyle::Plain), "10.0.0.0/8");
+
+        let query = Query::parse("--sources=RIPE AS3333");
+        assert_eq!(query.resource, "AS3333");
+        assert_eq!(query.translate(QueryStyle::Ripe), "--sources RIPE AS3333");
+    }
+
+    #[test]
+    fn query_arin_keywords() {
+        let query = Query::parse("n + 192.0.2.1");
+        assert_eq!(query.resource, "192.0.2.1");
+        assert_eq!(query.arin_keywords, vec!["n", "+"]);
+        assert_eq!(query.translate(QueryStyle::Arin), "n + 192.0.2.1");
+        assert_eq!(query.translate(QueryStyle::Ripe), "192.0.2.1");
+
+        let query = Query::parse("a 64496");
+        assert_eq!(query.resource, "AS64496");
+        assert_eq!(query.translate(QueryStyle::Arin), "a 64496");
+        assert_eq!(query.translate(QueryStyle::Ripe), "AS64496");
+
+        /* A keyword on its own is treated as the resource. */
+        let query = Query::parse("n");
+        assert_eq!(query.resource, "n");
+        assert!(!query.has_options());
+    }
+
+    #[test]
+    fn query_styles() {
+        assert_eq!(query_style("whois.ripe.net"),   QueryStyle::Ripe);
+        assert_eq!(query_style("WHOIS.APNIC.NET"),  QueryStyle::Ripe);
+        assert_eq!(query_style("whois.arin.net"),   QueryStyle::Arin);
+        assert_eq!(query_style("whois.lacnic.net"), QueryStyle::Plain);
+    }
+
+    #[test]
+    fn query_with_resource() {
+        let query = Query::parse("-r -T inetnum 10.0.0.0/8");
+        let part = query.with_resource("10.0.0.0/9");
+        assert_eq!(part.resource, "10.0.0.0/9");
+        assert_eq!(part.original, "-r -T inetnum 10.0.0.0/9");
+        assert_eq!(part.flags, query.flags);
+
+        let query = Query::parse("n + 192.0.2.0/23");
+        let part = query.with_resource("192.0.2.0/24");
+        assert_eq!(part.original, "n + 192.0.2.0/24");
+        assert_eq!(part.translate(QueryStyle::Arin), "n + 192.0.2.0/24");
+    }
+}
@@ -0,0 +1,120 @@
+extern crate owhois;
+
+#[cfg(test)]
+mod test_ratelimit {
+    use owhois::ratelimit::{Decision, LimitAction, RateLimitConfig,
+                            RateLimiter};
+    use std::net::IpAddr;
+    use std::str::FromStr;
+    use std::time::{Duration, Instant};
+
+    fn address(value: &str) -> IpAddr {
+        IpAddr::from_str(value).unwrap()
+    }
+
+    fn config() -> RateLimitConfig {
+        RateLimitConfig {
+            rate:            1.0,
+            burst:           2.0,
+            ipv4_prefix_len: 24,
+            ..RateLimitConfig::default()
+        }
+    }
+
+    #[test]
+    fn ratelimit_disabled() {
+        let mut limiter = RateLimiter::new(RateLimitConfig::default());
+        let now = Instant::now();
+        for _ in 0..100 {
+            assert_eq!(limiter.query(address("192.0.2.1"), now),
+                       Decision::Allow);
+            assert_eq!(limiter.connect(address("192.0.2.1"), now),
+                       Decision::Allow);
+        }
+    }
+
+    #[test]
+    fn ratelimit_client_key() {
+        let limiter = RateLimiter::new(config());
+        assert_eq!(limiter.client_key(address("192.0.2.200")),
+                   address("192.0.2.0"));
+        assert_eq!(limiter.client_key(address("2001:db8::1:2:3:4")),
+                   address("2001:db8::"));
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid IPv4 client prefix length: 40")]
+    fn ratelimit_invalid_ipv4_prefix() {
+        RateLimiter::new(RateLimitConfig { ipv4_prefix_len: 40, ..config() });
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid IPv6 client prefix length: 129")]
+    fn ratelimit_invalid_ipv6_prefix() {
+        RateLimiter::new(RateLimitConfig { ipv6_prefix_len: 129, ..config() });
+    }
+
+    #[test]
+    fn ratelimit_tokens() {
+        let mut limiter = RateLimiter::new(config());
+        let now = Instant::now();
+        assert_eq!(limiter.query(address("192.0.2.1"), now), Decision::Allow);
+        assert_eq!(limiter.query(address("192.0.2.2"), now), Decision::Allow);
+        assert_eq!(limiter.query(address("192.0.2.3"), now),
+                   Decision::Reject("query rate limit exceeded".to_owned()));
+        /* Other aggregates are not affected. */
+        assert_eq!(limiter.query(address("198.51.100.1"), now),
+                   Decision::Allow);
+
+        let later = now + Duration::from_millis(1500);
+        assert_eq!(limiter.query(address("192.0.2.1"), later),
+                   Decision::Allow);
+        assert_eq!(limiter.query(address("192.0.2.1"), later),
+                   Decision::Reject("query rate limit exceeded".to_owned()));
+    }
+
+    #[test]
+    fn ratelimit_connections() {
+        let mut config = config();
+        config.max_connections = 1;
+        config.action = LimitAction::Tarpit;
+        let mut limiter = RateLimiter::new(config);
+        let now = Instant::now();
+
+        assert_eq!(limiter.connect(address("192.0.2.1"), now),
+                   Decision::Allow);
+        assert_eq!(limiter.connect(address("192.0.2.2"), now),
+                   Decision::Tarpit(Duration::from_secs(10),
+                                    "too many connections".to_owned()));
+        limiter.disconnect(address("192.0.2.2"));
+        limiter.disconnect(address("192.0.2.1"));
+        assert_eq!(limiter.connect(address("192.0.2.1"), now),
+                   Decision::Allow);
+    }
+
+    #[test]
+    fn ratelimit_ban() {
+        let mut config = config();
+        config.action = LimitAction::Ban;
+        config.ban_duration = Duration::from_secs(60);
+        let mut limiter = RateLimiter::new(config);
+        let now = Instant::now();
+
+        limiter.query(address("192.0.2.1"), now);
+        limiter.query(address("192.0.2.1"), now);
+        assert_eq!(limiter.query(address("192.0.2.1"), now),
+                   Decision::Reject("query rate limit exceeded".to_owned()));
+
+        let later = now + Duration::from_secs(30);
+        assert_eq!(limiter.connect(address("192.0.2.1"), later),
+                   Decision::Reject("client is temporarily banned".to_owned()));
+        assert_eq!(limiter.query(address("192.0.2.1"), later),
+                   Decision::Reject("client is temporarily banned".to_owned()));
+
+        let later = now + Duration::from_secs(61);
+        assert_eq!(limiter.connect(address("192.0.2.1"), later),
+                   Decision::Allow);
+        assert_eq!(limiter.query(address("192.0.2.1"), later),
+                   Decision::Allow);
+    }
+}
@@ -0,0 +1,61 @@
+extern crate owhois;
+
+#[cfg(test)]
+mod test_referral {
+    use owhois::referral::parse_referral;
+
+    #[test]
+    fn referral_none() {
+        let response = "% This is a comment\n\
+                        inetnum: 192.0.2.0 - 192.0.2.255\n\
+                        netname: TEST-NET-1\n";
+        assert_eq!(parse_referral(response), None);
+    }
+
+    #[test]
+    fn referral_iana() {
+        let response = "% IANA WHOIS server\n\n\
+                        refer:        whois.arin.net\n\n\
+                        inetnum:      192.0.0.0 - 192.255.255.255\n";
+        assert_eq!(parse_referral(response),
+                   Some("whois.arin.net".to_owned()));
+
+        let response = "domain:       JP\n\
+                        whois:        WHOIS.JPRS.JP\n";
+        assert_eq!(parse_referral(response),
+                   Some("whois.jprs.jp".to_owned()));
+    }
+
+    #[test]
+    fn referral_arin() {
+        let response = "NetRange:       1.0.0.0 - 1.255.255.255\n\
+                        ReferralServer:  whois://whois.apnic.net\n";
+        assert_eq!(parse_referral(response),
+                   Some("whois.apnic.net".to_owned()));
+
+        let response = "ReferralServer:  whois://whois.example.net:4343/\n";
+        assert_eq!(parse_referral(response),
+                   Some("whois.example.net:4343".to_owned()));
+    }
+
+    #[test]
+    fn referral_rwhois() {
+        let response = "ReferralServer:  rwhois://RWHOIS.EXAMPLE.NET:4321/\n";
+        assert_eq!(parse_referral(response),
+                   Some("rwhois://rwhois.example.net".to_owned()));
+
+        let response = "ReferralServer:  rwhois://rwhois.example.net:4322\n";
+        assert_eq!(parse_referral(response),
+                   Some("rwhois://rwhois.example.net:4322".to_owned()));
+    }
+
+    #[test]
+    fn referral_unsupported() {
+        let response = "ReferralServer:  http://whois.example.net/\n\
+                        refer:\n";
+        assert_eq!(parse_referral(response), None);
+
+        let response = "% refer: whois.example.net\n";
+        assert_eq!(parse_referral(response), None);
+    }
+}
@@ -0,0 +1,170 @@
+extern crate owhois;
+extern crate tokio_core;
+
+#[cfg(test)]
+mod test_resolver {
+    use owhois::resolver::{AddressFamily, HostsTable, Resolver,
+                           ResolverConfig, connect_addresses,
+                           order_addresses};
+    use std::net::{IpAddr, SocketAddr, TcpListener, UdpSocket};
+    use std::str::FromStr;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::Duration;
+    use tokio_core::reactor::Core;
+
+    fn addresses(values: &[&str]) -> Vec<IpAddr> {
+        values.iter().map(|v| IpAddr::from_str(v).unwrap()).collect()
+    }
+
+    #[test]
+    fn resolver_order_addresses() {
+        let input = addresses(&["192.0.2.1", "192.0.2.2", "192.0.2.3",
+                                "2001:db8::1", "2001:db8::2"]);
+        assert_eq!(order_addresses(&input, AddressFamily::Any),
+                   addresses(&["192.0.2.1", "2001:db8::1", "192.0.2.2",
+                               "2001:db8::2", "192.0.2.3"]));
+        assert_eq!(order_addresses(&input, AddressFamily::PreferIpv6),
+                   addresses(&["2001:db8::1", "192.0.2.1", "2001:db8::2",
+                               "192.0.2.2", "192.0.2.3"]));
+        assert_eq!(order_addresses(&input, AddressFamily::Ipv4),
+                   addresses(&["192.0.2.1", "192.0.2.2", "192.0.2.3"]));
+        assert_eq!(order_addresses(&input, AddressFamily::Ipv6),
+                   addresses(&["2001:db8::1", "2001:db8::2"]));
+        assert_eq!(order_addresses(&addresses(&["192.0.2.1"]),
+                                   AddressFamily::Ipv6),
+                   Vec::<IpAddr>::new());
+    }
+
+    #[test]
+    fn resolver_hosts() {
+        let hosts = HostsTable::from_file("testdata/test_resolver/hosts_1");
+        assert_eq!(hosts.get("whois.example.net"),
+                   Some(&addresses(&["192.0.2.1", "2001:db8::1"])));
+        assert_eq!(hosts.get("WHOIS.EXAMPLE.ORG"),
+                   Some(&addresses(&["198.51.100.1", "198.51.100.2"])));
+        assert_eq!(hosts.get("whois.example.com"), None);
+
+        let mut core = Core::new().unwrap();
+        let config = ResolverConfig {
+            family: AddressFamily::PreferIpv6,
+            hosts,
+            ..ResolverConfig::default()
+        };
+        let resolver = Resolver::new(config, &core.handle());
+        assert_eq!(core.run(resolver.resolve("whois.example.net")).unwrap(),
+                   addresses(&["2001:db8::1", "192.0.2.1"]));
+        assert_eq!(core.run(resolver.resolve("203.0.113.1")).unwrap(),
+                   addresses(&["203.0.113.1"]));
+    }
+
+    /* Run a nameserver that answers A queries with 192.0.2.1 and the
+     * given TTL, and other queries with no records, and return its
+     * address and the number of queries it has received. */
+    fn nameserver(ttl: u32) -> (SocketAddr, Arc<AtomicUsize>) {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+        let address = socket.local_addr().unwrap();
+        let count = Arc::new(AtomicUsize::new(0));
+        let count_ = count.clone();
+        thread::spawn(move || {
+            let mut buffer = [0; 512];
+            while let Ok((size, peer)) = socket.recv_from(&mut buffer) {
+                count_.fetch_add(1, Ordering::SeqCst);
+                let mut response = buffer[..size].to_vec();
+                response[2] = 0x81;
+                response[3] = 0x80;
+                if response[size - 3] == 1 {
+                    response[7] = 1;
+                    response.extend_from_slice(&[0xC0, 12, 0, 1, 0, 1]);
+                    response.extend_from_slice(&[(ttl >> 24) as u8,
+                                                 (ttl >> 16) as u8,
+                                                 (ttl >> 8) as u8, ttl as u8]);
+                    response.extend_from_slice(&[0, 4, 192, 0, 2, 1]);
+                }
+                socket.send_to(&response, peer).unwrap();
+            }
+        });
+        (address, count)
+    }
+
+    #[test]
+    fn resolver_record_ttl() {
+        let mut core = Core::new().unwrap();
+        let resolve = |core: &mut Core, resolver: &Resolver| {
+            core.run(resolver.resolve("whois.example.net")).unwrap()
+        };
+
+        /* Addresses are cached for the TTL of the record. */
+        let (address, count) = nameserver(1);
+        let config = ResolverConfig {
+            nameservers: vec![address],
+            ..ResolverConfig::default()
+        };
+        let resolver = Resolver::new(config, &core.handle());
+        assert_eq!(resolve(&mut core, &resolver), addresses(&["192.0.2.1"]));
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+        assert_eq!(resolve(&mut core, &resolver), addresses(&["192.0.2.1"]));
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+        thread::sleep(Duration::from_millis(1100));
+        assert_eq!(resolve(&mut core, &resolver), addresses(&["192.0.2.1"]));
+        assert_eq!(count.load(Ordering::SeqCst), 4);
+
+        /* The cache TTL is the maximum time for which addresses are
+         * cached. */
+        let (address, count) = nameserver(3600);
+        let config = ResolverConfig {
+            nameservers: vec![address],
+            cache_ttl:   Duration::from_millis(500),
+            ..ResolverConfig::default()
+        };
+        let resolver = Resolver::new(config, &core.handle());
+        assert_eq!(resolve(&mut core, &resolver), addresses(&["192.0.2.1"]));
+        assert_eq!(resolve(&mut core, &resolver), addresses(&["192.0.2.1"]));
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+        thread::sleep(Duration::from_millis(600));
+        assert_eq!(resolve(&mut core, &resolver), addresses(&["192.0.2.1"]));
+        assert_eq!(count.load(Ordering::SeqCst), 4);
+    }
+
+    #[test]
+    fn resolver_family_only() {
+        let mut core = Core::new().unwrap();
+        let config = ResolverConfig {
+            family: AddressFamily::Ipv6,
+            ..ResolverConfig::default()
+        };
+        let resolver = Resolver::new(config, &core.handle());
+        assert!(core.run(resolver.resolve("192.0.2.1")).is_err());
+    }
+
+    #[test]
+    fn resolver_connect_fallback() {
+        /* The first address refuses the connection, so the second
+         * address is tried. */
+        let closed = TcpListener::bind("127.0.0.1:0").unwrap();
+        let closed_address = closed.local_addr().unwrap();
+        drop(closed);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let listener_address = listener.local_addr().unwrap();
+
+        let mut core = Core::new().unwrap();
+        let handle = core.handle();
+        let stream =
+            core.run(connect_addresses(vec![closed_address, listener_address],
+                                       Duration::from_secs(10), &handle))
+                .unwrap();
+        assert_eq!(stream.peer_addr().unwrap(), listener_address);
+
+        let result =
+            core.run(connect_addresses(vec![closed_address],
+                                       Duration::from_secs(10), &handle));
+        assert!(result.is_err());
+
+        let result =
+            core.run(connect_addresses(Vec::<SocketAddr>::new(),
+                                       Duration::from_secs(10), &handle));
+        assert!(result.is_err());
+    }
+}
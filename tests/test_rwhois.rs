@@ -0,0 +1,112 @@
+extern crate futures;
+extern crate owhois;
+extern crate tokio_core;
+
+#[cfg(test)]
+mod test_rwhois {
+    use futures::Future;
+    use owhois::rwhois::{is_final_line, query, read_banner,
+                         translate_response};
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread;
+    use tokio_core::net::TcpStream;
+    use tokio_core::reactor::Core;
+
+    #[test]
+    fn rwhois_final_line() {
+        assert!(is_final_line("%ok"));
+        assert!(is_final_line("%OK"));
+        assert!(is_final_line("%error 230 No Objects Found"));
+        assert!(!is_final_line("%referral rwhois://rwhois.example.net:4321"));
+        assert!(!is_final_line("network:ID:NET-192-0-2-0-1"));
+    }
+
+    #[test]
+    fn rwhois_translate() {
+        let response = "network:Class-Name:network\r\n\
+                        network:ID:NET-192-0-2-0-1.example.net\r\n\
+                        network:IP-Network:192.0.2.0/24\r\n\
+                        network:Tech-Contact;I:admin.example.net\r\n\
+                        \r\n\
+                        %referral rwhois://rwhois.example.org:4321/auth-area=192.0.2.0/24\r\n\
+                        %ok\r\n";
+        assert_eq!(translate_response(response),
+                   "Class-Name:     network\n\
+                    ID:             NET-192-0-2-0-1.example.net\n\
+                    IP-Network:     192.0.2.0/24\n\
+                    Tech-Contact:   admin.example.net\n\
+                    \n\
+                    ReferralServer: rwhois://rwhois.example.org:4321\n");
+
+        assert_eq!(translate_response("%error 230 No Objects Found\r\n"),
+                   "% RWhois error 230 No Objects Found\n");
+        assert_eq!(translate_response("%info on\r\nsome text\r\n%info off\r\n"),
+                   "% info on\nsome text\n% info off\n");
+    }
+
+    /* Run an RWhois server that sends the given banner and response,
+     * and return the response as returned by the client, along with
+     * the lines received by the server. */
+    fn exchange(banner: &'static str,
+                response: &'static str) -> (Result<String, String>,
+                                            Vec<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.write_all(banner.as_bytes()).unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut lines = Vec::new();
+            let mut line = String::new();
+            if reader.read_line(&mut line).unwrap() > 0 {
+                lines.push(line.trim().to_owned());
+                stream.write_all(response.as_bytes()).unwrap();
+                line.clear();
+                if reader.read_line(&mut line).unwrap() > 0 {
+                    lines.push(line.trim().to_owned());
+                }
+            }
+            lines
+        });
+
+        let mut core = Core::new().unwrap();
+        let handle = core.handle();
+        let client =
+            TcpStream::connect(&address, &handle)
+                .and_then(read_banner)
+                .and_then(|(stream, buffer)| {
+                    query(stream, buffer, "192.0.2.1\r\n".to_owned())
+                })
+                .map(|response| String::from_utf8(response).unwrap())
+                .map_err(|e| e.to_string());
+        let result = core.run(client);
+        (result, server.join().unwrap())
+    }
+
+    #[test]
+    fn rwhois_query() {
+        let (result, lines) =
+            exchange("%rwhois V-1.5:003fff:00 rwhois.example.net\r\n",
+                     "network:IP-Network:192.0.2.0/24\r\n%ok\r\n");
+        assert_eq!(result.unwrap(), "IP-Network:     192.0.2.0/24\n");
+        assert_eq!(lines, vec!["192.0.2.1", "-quit"]);
+
+        let (result, _) =
+            exchange("%rwhois V-1.5:003fff:00 rwhois.example.net\r\n",
+                     "%error 230 No Objects Found\r\n");
+        assert_eq!(result.unwrap(), "% RWhois error 230 No Objects Found\n");
+    }
+
+    #[test]
+    fn rwhois_bad_banner() {
+        let (result, lines) = exchange("% Whois server\r\n", "");
+        assert_eq!(result.unwrap_err(),
+                   "unexpected RWhois banner: % Whois server");
+        assert!(lines.is_empty());
+
+        let (result, _) = exchange("%error 503 Service Not Available\r\n", "");
+        assert_eq!(result.unwrap_err(),
+                   "RWhois server error: 503 Service Not Available");
+    }
+}
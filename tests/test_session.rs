@@ -0,0 +1,146 @@
+extern crate futures;
+extern crate owhois;
+extern crate tokio_core;
+
+#[cfg(test)]
+mod test_session {
+    use futures::{Future, Stream};
+    use futures::stream;
+    use futures::unsync::mpsc;
+    use owhois::input::Input;
+    use owhois::session::{Session, is_bulk_begin, keep_alive_query,
+                          session_block, session_inputs, with_idle_timeout};
+    use std::io;
+    use std::time::{Duration, Instant};
+    use tokio_core::reactor::Core;
+
+    fn query(line: &str) -> Input {
+        Input::Query(line.to_owned())
+    }
+
+    /* Return the items of the session, with errors replaced by their
+     * kinds. */
+    fn inputs(session: Session,
+              lines: Vec<io::Result<Input>>) -> Vec<Result<Input, io::ErrorKind>> {
+        let lines = stream::iter_result(lines);
+        session_inputs(session, lines)
+            .map(|item| item.map_err(|e| e.kind()))
+            .collect()
+            .wait()
+            .unwrap()
+    }
+
+    #[test]
+    fn session_start() {
+        assert!(is_bulk_begin("begin"));
+        assert!(is_bulk_begin(" BEGIN \r"));
+        assert!(!is_bulk_begin("begin 192.0.2.1"));
+        assert_eq!(Session::start("Begin"), Some((Session::Bulk, None)));
+
+        assert_eq!(keep_alive_query("-k"), Some(""));
+        assert_eq!(keep_alive_query(" -k  192.0.2.1 "), Some("192.0.2.1"));
+        assert_eq!(keep_alive_query("--persistent-connection AS64496"),
+                   Some("AS64496"));
+        assert_eq!(keep_alive_query("-k\t-B 192.0.2.1"), Some("-B 192.0.2.1"));
+        assert_eq!(keep_alive_query("-kB 192.0.2.1"), None);
+        assert_eq!(keep_alive_query("-K 192.0.2.1"), None);
+        assert_eq!(keep_alive_query("192.0.2.1 -k"), None);
+        assert_eq!(Session::start("-k 192.0.2.1"),
+                   Some((Session::KeepAlive, Some(query("192.0.2.1")))));
+        assert_eq!(Session::start("-k"),
+                   Some((Session::KeepAlive, Some(query("")))));
+
+        assert_eq!(Session::start("192.0.2.1"), None);
+        assert_eq!(Session::start(""), None);
+    }
+
+    #[test]
+    fn session_end() {
+        assert!(Session::Bulk.is_end(&query("end")));
+        assert!(Session::Bulk.is_end(&query(" END ")));
+        assert!(!Session::Bulk.is_end(&query("-k")));
+        assert!(!Session::Bulk.is_end(&Input::TooLong));
+
+        assert!(Session::KeepAlive.is_end(&query("-k")));
+        assert!(Session::KeepAlive.is_end(&query("--persistent-connection ")));
+        assert!(!Session::KeepAlive.is_end(&query("-k 192.0.2.1")));
+        assert!(!Session::KeepAlive.is_end(&query("end")));
+        assert!(!Session::KeepAlive.is_end(&Input::Invalid));
+    }
+
+    #[test]
+    fn session_query() {
+        assert_eq!(Session::Bulk.query(" 192.0.2.1 ".to_owned()),
+                   (" 192.0.2.1 ".to_owned(), Some("192.0.2.1".to_owned())));
+        assert_eq!(Session::KeepAlive.query("-k 192.0.2.1".to_owned()),
+                   ("192.0.2.1".to_owned(), None));
+        assert_eq!(Session::KeepAlive.query("192.0.2.1".to_owned()),
+                   ("192.0.2.1".to_owned(), None));
+
+        assert_eq!(Session::Bulk.concurrency(0), 1);
+        assert_eq!(Session::Bulk.concurrency(8), 8);
+        assert_eq!(Session::KeepAlive.concurrency(8), 1);
+    }
+
+    #[test]
+    fn session_inputs_blank_lines() {
+        /* Blank lines are skipped, and the session stops at the end
+         * line, ignoring anything after it. */
+        let items = inputs(Session::Bulk, vec![
+            Ok(query("192.0.2.1")), Ok(query("")), Ok(query("  \t")),
+            Ok(Input::TooLong), Ok(query("AS64496")), Ok(query("end")),
+            Ok(query("192.0.2.2")),
+        ]);
+        assert_eq!(items, vec![Ok(query("192.0.2.1")), Ok(Input::TooLong),
+                               Ok(query("AS64496"))]);
+
+        let items = inputs(Session::KeepAlive, vec![
+            Ok(query("")), Ok(query("-k 192.0.2.1")), Ok(query("")),
+            Ok(query("end")), Ok(query("-k")), Ok(query("192.0.2.2")),
+        ]);
+        assert_eq!(items, vec![Ok(query("-k 192.0.2.1")), Ok(query("end"))]);
+
+        /* Errors are passed through as items. */
+        let items = inputs(Session::Bulk, vec![
+            Ok(query("192.0.2.1")),
+            Err(io::Error::new(io::ErrorKind::TimedOut, "timed out")),
+        ]);
+        assert_eq!(items, vec![Ok(query("192.0.2.1")),
+                               Err(io::ErrorKind::TimedOut)]);
+    }
+
+    #[test]
+    fn session_block_format() {
+        assert_eq!(session_block(Some("192.0.2.1"), b"data\n".to_vec()),
+                   b"% Query: 192.0.2.1\ndata\n% End of response\n".to_vec());
+        assert_eq!(session_block(None, b"data".to_vec()),
+                   b"data\n% End of response\n".to_vec());
+        assert_eq!(session_block(None, Vec::new()),
+                   b"% End of response\n".to_vec());
+    }
+
+    #[test]
+    fn session_idle_timeout() {
+        let mut core = Core::new().unwrap();
+        let handle = core.handle();
+        let (sender, receiver) = mpsc::unbounded::<Input>();
+        sender.unbounded_send(query("192.0.2.1")).unwrap();
+
+        let lines = receiver.map_err(|_| io::Error::other("closed"));
+        let lines = with_idle_timeout(lines, Duration::from_millis(100),
+                                      "idle".to_owned(), &handle).unwrap();
+        let start = Instant::now();
+        let items: Vec<Result<Input, (io::ErrorKind, String)>> =
+            core.run(lines.then(|item| {
+                    Ok::<_, io::Error>(item.map_err(|e| {
+                        (e.kind(), e.to_string())
+                    }))
+                })
+                .collect()).unwrap();
+        assert_eq!(items, vec![Ok(query("192.0.2.1")),
+                               Err((io::ErrorKind::TimedOut,
+                                    "idle".to_owned()))]);
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        drop(sender);
+    }
+}
@@ -0,0 +1,100 @@
+extern crate owhois;
+
+#[cfg(test)]
+mod test_target {
+    use owhois::target::{UpstreamProtocol, UpstreamTarget, canonical_server};
+    use std::str::FromStr;
+
+    fn target(protocol: UpstreamProtocol, host: &str,
+              port: u16) -> UpstreamTarget {
+        UpstreamTarget { protocol, host: host.to_owned(), port, tls: false }
+    }
+
+    fn parse(value: &str) -> UpstreamTarget {
+        UpstreamTarget::from_str(value).unwrap()
+    }
+
+    #[test]
+    fn target_parse() {
+        let whois = UpstreamProtocol::Whois;
+        assert_eq!(parse("whois.example.net"),
+                   target(whois, "whois.example.net", 43));
+        assert_eq!(parse("WHOIS.Example.NET:4343"),
+                   target(whois, "whois.example.net", 4343));
+        assert_eq!(parse("192.0.2.1"), target(whois, "192.0.2.1", 43));
+        assert_eq!(parse("192.0.2.1:4343"), target(whois, "192.0.2.1", 4343));
+        assert_eq!(parse("2001:db8::1"), target(whois, "2001:db8::1", 43));
+        assert_eq!(parse("[2001:DB8::1]:4343"),
+                   target(whois, "2001:db8::1", 4343));
+        assert_eq!(parse("whois://whois.example.net/"),
+                   target(whois, "whois.example.net", 43));
+        assert_eq!(parse("WHOIS://[2001:db8::1]:4343"),
+                   target(whois, "2001:db8::1", 4343));
+        assert_eq!(parse("rwhois://rwhois.example.net"),
+                   target(UpstreamProtocol::Rwhois, "rwhois.example.net",
+                          4321));
+        assert_eq!(parse("rwhois://rwhois.example.net:4322/"),
+                   target(UpstreamProtocol::Rwhois, "rwhois.example.net",
+                          4322));
+    }
+
+    #[test]
+    fn target_parse_tls() {
+        let tls_target = |protocol, host, port| {
+            UpstreamTarget { tls: true, ..target(protocol, host, port) }
+        };
+        assert_eq!(parse("whois+tls://whois.example.net:4343"),
+                   tls_target(UpstreamProtocol::Whois, "whois.example.net",
+                              4343));
+        assert_eq!(parse("WHOIS+TLS://2001:db8::1/"),
+                   tls_target(UpstreamProtocol::Whois, "2001:db8::1", 43));
+        assert_eq!(parse("rwhois+tls://rwhois.example.net"),
+                   tls_target(UpstreamProtocol::Rwhois, "rwhois.example.net",
+                              4321));
+        assert!(UpstreamTarget::from_str("http+tls://whois.example.net")
+                    .is_err());
+
+        assert_eq!(canonical_server("whois+tls://Whois.Example.NET:43/"),
+                   "whois+tls://whois.example.net");
+        assert_eq!(canonical_server("rwhois+tls://[2001:db8::1]:4343"),
+                   "rwhois+tls://[2001:db8::1]:4343");
+        for value in &["whois+tls://whois.example.net:4343",
+                       "rwhois+tls://192.0.2.1"] {
+            let target = parse(value);
+            assert_eq!(parse(&target.to_string()), target);
+        }
+    }
+
+    #[test]
+    fn target_parse_invalid() {
+        for value in &["", "whois://", "http://whois.example.net/",
+                       "whois.example.net:port", "whois.example.net:65536",
+                       "[2001:db8::1", "whois.example.net/path",
+                       "whois example.net"] {
+            assert!(UpstreamTarget::from_str(value).is_err(),
+                    "'{}' should be invalid", value);
+        }
+    }
+
+    #[test]
+    fn target_canonical() {
+        assert_eq!(canonical_server("whois.example.net:43"),
+                   "whois.example.net");
+        assert_eq!(canonical_server("whois://Whois.Example.NET:4343/"),
+                   "whois.example.net:4343");
+        assert_eq!(canonical_server("2001:db8::1"), "2001:db8::1");
+        assert_eq!(canonical_server("[2001:db8::1]:4343"),
+                   "[2001:db8::1]:4343");
+        assert_eq!(canonical_server("rwhois://rwhois.example.net:4321"),
+                   "rwhois://rwhois.example.net");
+        assert_eq!(canonical_server("rwhois://[2001:db8::1]:43"),
+                   "rwhois://[2001:db8::1]:43");
+
+        /* The canonical form parses to the same target. */
+        for value in &["whois.example.net:4343", "[2001:db8::1]:4343",
+                       "rwhois://192.0.2.1:4343"] {
+            let target = parse(value);
+            assert_eq!(parse(&target.to_string()), target);
+        }
+    }
+}
@@ -0,0 +1,154 @@
+extern crate futures;
+extern crate openssl;
+extern crate owhois;
+extern crate tokio_core;
+extern crate tokio_io;
+extern crate tokio_openssl;
+
+#[cfg(test)]
+mod test_tls {
+    use futures::{Future, Stream};
+    use openssl::ssl::{SslConnector, SslFiletype, SslMethod};
+    use owhois::tls::{TlsAcceptor, TlsConfig, TlsConnectors, build_acceptor,
+                      build_connector};
+    use std::io;
+    use std::path::{Path, PathBuf};
+    use tokio_core::net::{TcpListener, TcpStream};
+    use tokio_core::reactor::Core;
+    use tokio_io::io::{read_to_end, write_all};
+    use tokio_openssl::SslConnectorExt;
+
+    fn config(key: &str, client_ca: Option<&str>) -> TlsConfig {
+        TlsConfig {
+            listen:      vec![],
+            certificate: PathBuf::from("testdata/test_tls/server.pem"),
+            key:         PathBuf::from(key),
+            client_ca:   client_ca.map(PathBuf::from),
+        }
+    }
+
+    /* Connect to a TLS acceptor, send a line, and return the line as
+     * received by the server. */
+    fn exchange(acceptor: &TlsAcceptor,
+                client_certificate: bool) -> io::Result<Vec<u8>> {
+        let mut core = Core::new().unwrap();
+        let handle = core.handle();
+        let listener =
+            TcpListener::bind(&"127.0.0.1:0".parse().unwrap(), &handle).unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let mut builder = SslConnector::builder(SslMethod::tls()).unwrap();
+        builder.set_ca_file("testdata/test_tls/ca.pem").unwrap();
+        if client_certificate {
+            builder.set_certificate_file("testdata/test_tls/client.pem",
+                                         SslFiletype::PEM).unwrap();
+            builder.set_private_key_file("testdata/test_tls/client_key.pem",
+                                         SslFiletype::PEM).unwrap();
+        }
+        let connector = builder.build();
+
+        let acceptor = acceptor.clone();
+        let server =
+            listener.incoming().into_future()
+                .map_err(|e| e.0)
+                .and_then(move |(connection, _)| {
+                    acceptor.accept(connection.unwrap().0)
+                })
+                .and_then(|stream| read_to_end(stream, Vec::new()))
+                .map(|(_, data)| data);
+        let client =
+            TcpStream::connect(&address, &handle)
+                .and_then(move |stream| {
+                    connector.connect_async("localhost", stream)
+                        .map_err(|e| io::Error::other(e.to_string()))
+                })
+                .and_then(|stream| write_all(stream, b"AS64496\r\n"))
+                .and_then(|(mut stream, _)| {
+                    stream.get_mut().shutdown().unwrap();
+                    Ok(())
+                });
+        core.run(server.join(client.then(|_| Ok(())))).map(|(data, _)| data)
+    }
+
+    #[test]
+    fn tls_build_acceptor() {
+        assert!(build_acceptor(&config("testdata/test_tls/server_key.pem",
+                                       None)).is_ok());
+        assert!(build_acceptor(&config("testdata/test_tls/client_key.pem",
+                                       None)).is_err());
+        assert!(build_acceptor(&config("testdata/test_tls/missing.pem",
+                                       None)).is_err());
+    }
+
+    #[test]
+    fn tls_handshake() {
+        let acceptor =
+            TlsAcceptor::new(&config("testdata/test_tls/server_key.pem",
+                                     None)).unwrap();
+        assert_eq!(exchange(&acceptor, false).unwrap(), b"AS64496\r\n");
+
+        /* A failed reload leaves the existing certificate in place. */
+        assert!(acceptor.reload(&config("testdata/test_tls/client_key.pem",
+                                        None)).is_err());
+        assert_eq!(exchange(&acceptor, false).unwrap(), b"AS64496\r\n");
+    }
+
+    #[test]
+    fn tls_client_certificate() {
+        let acceptor =
+            TlsAcceptor::new(&config("testdata/test_tls/server_key.pem",
+                                     Some("testdata/test_tls/ca.pem")))
+                .unwrap();
+        assert_eq!(exchange(&acceptor, true).unwrap(), b"AS64496\r\n");
+        assert!(exchange(&acceptor, false).is_err());
+    }
+
+    /* Connect to a TLS acceptor using the upstream connectors, and
+     * return whether the handshake succeeded. */
+    fn upstream_handshake(host: &str, ca_file: Option<&Path>) -> bool {
+        let mut core = Core::new().unwrap();
+        let handle = core.handle();
+        let listener =
+            TcpListener::bind(&"127.0.0.1:0".parse().unwrap(), &handle).unwrap();
+        let address = listener.local_addr().unwrap();
+        let acceptor =
+            TlsAcceptor::new(&config("testdata/test_tls/server_key.pem",
+                                     None)).unwrap();
+
+        let server =
+            listener.incoming().into_future()
+                .map_err(|e| e.0)
+                .and_then(move |(connection, _)| {
+                    acceptor.accept(connection.unwrap().0)
+                })
+                .then(|_| Ok(()));
+        let connectors = TlsConnectors::new();
+        let host = host.to_owned();
+        let ca_file = ca_file.map(|path| path.to_path_buf());
+        let client =
+            TcpStream::connect(&address, &handle)
+                .and_then(move |stream| {
+                    connectors.connect(&host, ca_file.as_deref(),
+                                       stream)
+                });
+        let (_, result): ((), io::Result<_>) =
+            core.run(server.join(client.then(Ok::<_, io::Error>))).unwrap();
+        result.is_ok()
+    }
+
+    #[test]
+    fn tls_upstream() {
+        assert!(build_connector(None).is_ok());
+        assert!(build_connector(Some(Path::new("testdata/test_tls/ca.pem")))
+                    .is_ok());
+        assert!(build_connector(Some(Path::new("testdata/test_tls/missing.pem")))
+                    .is_err());
+
+        let ca_file = Path::new("testdata/test_tls/ca.pem");
+        assert!(upstream_handshake("localhost", Some(ca_file)));
+        /* The test CA is not trusted by the system, and the
+         * certificate is not valid for other names. */
+        assert!(!upstream_handshake("localhost", None));
+        assert!(!upstream_handshake("whois.example.net", Some(ca_file)));
+    }
+}
@@ -0,0 +1,88 @@
+extern crate owhois;
+
+#[cfg(test)]
+mod test_upstream {
+    use owhois::query::Query;
+    use owhois::upstream::{Timeouts, UpstreamConfig};
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    #[test]
+    fn upstream_default() {
+        let config = UpstreamConfig::default();
+        let query = Query::parse("-B 192.0.2.1");
+        assert_eq!(config.rewrite("whois.ripe.net", &query), "-B 192.0.2.1");
+        assert_eq!(config.rewrite("whois.arin.net", &query), "192.0.2.1");
+    }
+
+    #[test]
+    fn upstream_templates() {
+        let config =
+            UpstreamConfig::from_file("testdata/test_upstream/upstream_1");
+
+        let query = Query::parse("192.0.2.1");
+        assert_eq!(config.rewrite("whois.arin.net", &query),
+                   "n + 192.0.2.1");
+        assert_eq!(config.rewrite("WHOIS.NIC.AD.JP", &query),
+                   "192.0.2.1/e");
+        assert_eq!(config.rewrite("whois.ripe.net", &query),
+                   "192.0.2.1");
+
+        /* Templates are not used when the client provides its own
+         * options. */
+        let query = Query::parse("n 192.0.2.1");
+        assert_eq!(config.rewrite("whois.arin.net", &query),
+                   "n 192.0.2.1");
+    }
+
+    #[test]
+    fn upstream_flags() {
+        let config =
+            UpstreamConfig::from_file("testdata/test_upstream/upstream_1");
+
+        let query = Query::parse("-b -T inetnum 192.0.2.1");
+        assert_eq!(config.rewrite("whois.example.net", &query),
+                   "-B -T inetnum 192.0.2.1");
+
+        let query = Query::parse("-r 192.0.2.1");
+        assert_eq!(config.rewrite("whois.example.net", &query),
+                   "192.0.2.1");
+    }
+
+    #[test]
+    fn upstream_timeouts() {
+        let config =
+            UpstreamConfig::from_file("testdata/test_upstream/upstream_1");
+        let defaults = Timeouts::default();
+
+        assert_eq!(config.timeouts("whois.ripe.net", defaults), defaults);
+
+        let timeouts = config.timeouts("whois.example.net", defaults);
+        assert_eq!(timeouts.connect, Duration::from_secs(2));
+        assert_eq!(timeouts.first_byte, defaults.first_byte);
+        assert_eq!(timeouts.total, defaults.total);
+    }
+
+    #[test]
+    fn upstream_tls() {
+        let config =
+            UpstreamConfig::from_file("testdata/test_upstream/upstream_1");
+        assert!(!config.get("whois.example.net").tls);
+        let options = config.get("WHOIS.EXAMPLE.ORG");
+        assert!(options.tls);
+        assert_eq!(options.tls_ca_file,
+                   Some(PathBuf::from("testdata/test_tls/ca.pem")));
+    }
+
+    #[test]
+    fn upstream_keep_alive() {
+        let config =
+            UpstreamConfig::from_file("testdata/test_upstream/upstream_1");
+        let options = config.get("whois.example.net");
+        assert!(options.keep_alive);
+        assert_eq!(options.pool_size, Some(8));
+        let options = config.get("whois.example.org");
+        assert!(!options.keep_alive);
+        assert_eq!(options.pool_size, None);
+    }
+}